@@ -1,52 +1,764 @@
 use proc_macro::{self, TokenStream};
+use quote::ToTokens;
 use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(Configr)]
-pub fn configr_no_default(input: TokenStream) -> TokenStream {
-	let DeriveInput { ident, data, .. } = parse_macro_input!(input);
-	if let syn::Data::Struct(s) = data {
-		if let syn::Fields::Named(f) = s.fields {
-			let fields: Vec<String> = f
-				.named
-				.into_iter()
-				.map(|f| f.ident.map(|i| i.to_string()).unwrap_or("".to_string()))
-				.collect();
-			return format!(
-				r#"impl Config<Self> for {} {{
-                fn populate_template(fd: std::fs::File) -> std::io::Result<()> {{
-                    use std::io::Write;
-                    let mut writer = std::io::BufWriter::new(fd);
-                    for f in &{:?} {{
-                        writer.write_fmt(format_args!("{{}}=\n", f))?;
+/// Implements `Config`, writing templates with the keys left empty,
+/// or from the struct's `Default` value with `#[configr(default)]`\
+/// `#[configr(generate_default)]` also implements `Default` from the
+/// fields' `#[configr(default = "...")]` values.
+#[proc_macro_derive(Configr, attributes(configr))]
+pub fn configr(input: TokenStream) -> TokenStream {
+	let DeriveInput {
+		ident,
+		generics,
+		data,
+		attrs,
+		..
+	} = parse_macro_input!(input);
+	if let Some(error) = shape_error(&ident, &data, "Configr") {
+		return error;
+	}
+	let generate_default = has_flag(&attrs, "generate_default");
+	let mut expanded = if generate_default || has_flag(&attrs, "default") {
+		default_impl(&ident, &generics, &data, &attrs)
+	} else {
+		empty_impl(&ident, &generics, &data, &attrs)
+	};
+	if generate_default {
+		expanded.push_str(&default_trait_impl(&ident, &generics, &data));
+	}
+	expanded.parse().unwrap()
+}
+
+/// The same as `Configr` with `#[configr(default)]`, kept for
+/// compatibility and warning that it is deprecated
+#[proc_macro_derive(ConfigrDefault, attributes(configr))]
+pub fn configr_default(input: TokenStream) -> TokenStream {
+	let DeriveInput {
+		ident,
+		generics,
+		data,
+		attrs,
+		..
+	} = parse_macro_input!(input);
+	if let Some(error) = shape_error(&ident, &data, "ConfigrDefault") {
+		return error;
+	}
+	// Proc macros can't be deprecated themselves, so use a deprecated
+	// item to get the warning at the derive.
+	format!(
+		r#"{}
+        const _: () = {{
+            #[deprecated(note = "use `#[derive(Configr)]` with `#[configr(default)]` instead")]
+            struct ConfigrDefault;
+            let _ = ConfigrDefault;
+        }};"#,
+		default_impl(&ident, &generics, &data, &attrs)
+	)
+	.parse()
+	.unwrap()
+}
+
+/// A `compile_error!` at the derive of `derive` if it's applied to
+/// anything but a struct with named fields, which would otherwise be
+/// left without an implementation
+fn shape_error(
+	ident: &syn::Ident,
+	data: &syn::Data,
+	derive: &str,
+) -> Option<TokenStream> {
+	let shape = match data {
+		syn::Data::Struct(syn::DataStruct {
+			fields: syn::Fields::Named(_),
+			..
+		}) => return None,
+		syn::Data::Struct(syn::DataStruct {
+			fields: syn::Fields::Unnamed(_),
+			..
+		}) => "a tuple struct",
+		syn::Data::Struct(_) => "a unit struct",
+		syn::Data::Enum(_) => "an enum",
+		syn::Data::Union(_) => "a union",
+	};
+	let message = format!(
+		"`#[derive({})]` only supports structs with named fields, but `{}` is {}",
+		derive, ident, shape
+	);
+
+	Some(syn::Error::new_spanned(ident, message).to_compile_error().into())
+}
+
+/// Implements `Config` with a template of the fields with empty
+/// values
+fn empty_impl(
+	ident: &syn::Ident,
+	generics: &syn::Generics,
+	data: &syn::Data,
+	attrs: &[syn::Attribute],
+) -> String {
+	let (params, ty, where_clause) = impl_generics(
+		ident,
+		generics,
+		&["Self: configr::__private::DeserializeOwned"],
+		"",
+	);
+	if let Some(fields) = named_fields(data) {
+		return format!(
+			r#"impl{} Config<Self> for {} {} {{
+                {}
+                {}
+                {}
+                fn populate_template(
+                    writer: impl std::io::Write,
+                ) -> std::result::Result<(), configr::ConfigError> {{
+                    Self::FORMAT.write_template(writer, &{}, &Self::template_fields())
+                        .map_err(|source| configr::ConfigError::Template {{ source }})
+                }}
+            }}
+            {}"#,
+			params,
+			ty,
+			where_clause,
+			format_const(attrs),
+			hooks(attrs),
+			template_fields(&fields),
+			str_slice(&doc_lines(attrs)),
+			app_impl(ident, generics, attrs)
+		);
+	}
+	String::new()
+}
+
+/// Implements `Config` with a template serialized from the struct's
+/// `Default` value, which also fills in keys missing from the file
+fn default_impl(
+	ident: &syn::Ident,
+	generics: &syn::Generics,
+	data: &syn::Data,
+	attrs: &[syn::Attribute],
+) -> String {
+	let (params, ty, where_clause) = impl_generics(
+		ident,
+		generics,
+		&[
+			"Self: configr::__private::DeserializeOwned",
+			"Self: configr::__private::Serialize + Default",
+		],
+		"",
+	);
+	let fields = named_fields(data);
+	let skipped: Vec<&str> = fields
+		.iter()
+		.flatten()
+		.filter(|f| f.skip)
+		.map(|f| f.key.as_str())
+		.collect();
+	// Going through a `Value` reorders the keys, so only do it when there
+	// are keys to remove.
+	let template = if skipped.is_empty() {
+		"Self::FORMAT.serialize::<Self>(&Default::default()).map_err(|source| \
+		 configr::ConfigError::TemplateSerialize { source })?"
+			.to_string()
+	} else {
+		format!(
+			r#"{{
+                let mut value = configr::toml::Value::try_from(<Self as Default>::default())
+                    .map_err(|source| configr::ConfigError::TemplateSerialize {{
+                        source: configr::FormatError::TomlSer {{ source }},
+                    }})?;
+                if let Some(table) = value.as_table_mut() {{
+                    for key in &{:?} {{
+                        table.remove(*key);
                     }}
-                    writer.flush()?;
-                    Ok(())
                 }}
+                Self::FORMAT.serialize(&value)
+                    .map_err(|source| configr::ConfigError::TemplateSerialize {{ source }})?
             }}"#,
-				ident, fields
-			)
-			.parse()
-			.unwrap();
-		}
+			skipped
+		)
+	};
+	format!(
+		r#"impl{} Config<Self> for {} {} {{
+		{}
+		{}
+		{}
+		fn populate_template(
+			mut writer: impl std::io::Write,
+		) -> std::result::Result<(), configr::ConfigError> {{
+			let content = {};
+			writer.write_all(Self::FORMAT.annotate(&content, &{}, &Self::template_fields()).as_bytes())
+				.and_then(|()| writer.flush())
+				.map_err(|source| configr::ConfigError::Template {{ source }})
+		}}
+		fn defaults() -> Option<configr::toml::Value> {{
+			configr::toml::Value::try_from(<Self as Default>::default()).ok()
+		}}
+	}}
+	{}"#,
+		params,
+		ty,
+		where_clause,
+		format_const(attrs),
+		hooks(attrs),
+		fields.as_deref().map(template_fields).unwrap_or_default(),
+		template,
+		str_slice(&doc_lines(attrs)),
+		app_impl(ident, generics, attrs)
+	)
+}
+
+/// Implements `Default` using each field's `#[configr(default =
+/// "...")]` value, read as TOML, and the `Default` of its type for
+/// the fields without one
+fn default_trait_impl(
+	ident: &syn::Ident,
+	generics: &syn::Generics,
+	data: &syn::Data,
+) -> String {
+	let (params, ty, where_clause) = impl_generics(
+		ident,
+		generics,
+		&[],
+		"Default + configr::__private::DeserializeOwned",
+	);
+	let fields: Vec<String> = named_fields(data)
+		.unwrap_or_default()
+		.iter()
+		.map(|f| match &f.default {
+			Some(default) => format!(
+				"{}: configr::__private::default_value::<{}>({:?}, {:?})",
+				f.ident,
+				f.ty.to_token_stream(),
+				default,
+				f.key
+			),
+			None => format!("{}: Default::default()", f.ident),
+		})
+		.collect();
+	format!(
+		r#"
+        impl{} Default for {} {} {{
+            fn default() -> Self {{
+                Self {{ {} }}
+            }}
+        }}"#,
+		params,
+		ty,
+		where_clause,
+		fields.join(", ")
+	)
+}
+
+/// The parameters after `impl`, the struct's type and the where
+/// clause of an impl for the struct `ident` with `generics`, adding
+/// `predicates` and bounding every type parameter by `bounds`, if not
+/// empty, on top of the struct's own where clause
+fn impl_generics(
+	ident: &syn::Ident,
+	generics: &syn::Generics,
+	predicates: &[&str],
+	bounds: &str,
+) -> (String, String, String) {
+	let (params, ty, where_clause) = generics.split_for_impl();
+	let mut all: Vec<String> = where_clause
+		.iter()
+		.flat_map(|w| w.predicates.iter())
+		.map(|p| p.to_token_stream().to_string())
+		.collect();
+	if !bounds.is_empty() {
+		all.extend(generics.type_params().map(|p| format!("{}: {}", p.ident, bounds)));
 	}
-	return "".parse().unwrap();
+	all.extend(predicates.iter().map(|p| p.to_string()));
+	let where_clause = if all.is_empty() {
+		String::new()
+	} else {
+		format!("where {}", all.join(", "))
+	};
+
+	(
+		params.to_token_stream().to_string(),
+		format!("{}{}", ident, ty.to_token_stream()),
+		where_clause,
+	)
 }
 
-#[proc_macro_derive(ConfigrDefault)]
-pub fn configr(input: TokenStream) -> TokenStream {
-	let DeriveInput { ident, .. } = parse_macro_input!(input);
+/// A named field of the struct along with its configr options
+struct Field {
+	ident: syn::Ident,
+	/// The key the field is stored under, which follows
+	/// `#[configr(rename = "...")]` and serde's `rename`
+	key: String,
+	ty: syn::Type,
+	/// Set by `#[configr(skip)]`, leaves the field out of templates
+	skip: bool,
+	/// The lines of the field's doc comment
+	doc: Vec<String>,
+	/// Set by `#[configr(default = "...")]`, written verbatim as the
+	/// field's value in templates
+	default: Option<String>,
+	/// Set by `#[configr(env = "...")]`, the environment variable
+	/// overriding the field
+	env: Option<String>,
+	/// Set by `#[configr(example = "...")]`, shown in a comment above
+	/// the field in templates
+	example: Option<String>,
+	/// Set by `#[configr(deprecated = "...")]`, the note warned about
+	/// when the key is present
+	deprecated: Option<String>,
+	/// Set by `#[configr(secret)]`, redacts the value when dumping
+	secret: bool,
+	/// Set by `#[serde(flatten)]`, the keys of the field's type are
+	/// inlined instead of being a table
+	flatten: bool,
+}
+
+/// Collects the named fields of the struct, or `None` if it has none
+fn named_fields(data: &syn::Data) -> Option<Vec<Field>> {
+	let fields = match data {
+		syn::Data::Struct(syn::DataStruct {
+			fields: syn::Fields::Named(f),
+			..
+		}) => f,
+		_ => return None,
+	};
+	Some(
+		fields
+			.named
+			.iter()
+			.filter_map(|f| f.ident.clone().map(|ident| (ident, f)))
+			.map(|(ident, f)| Field {
+				ident,
+				key: attr_value(&f.attrs, "rename")
+					.or_else(|| serde_rename(&f.attrs))
+					.or_else(|| {
+						f.ident
+							.as_ref()
+							.map(|i| i.to_string().trim_start_matches("r#").to_string())
+					})
+					.unwrap_or_default(),
+				ty: f.ty.clone(),
+				skip: has_flag(&f.attrs, "skip"),
+				doc: doc_lines(&f.attrs),
+				default: attr_value(&f.attrs, "default"),
+				env: attr_value(&f.attrs, "env"),
+				example: attr_value(&f.attrs, "example"),
+				deprecated: attr_value(&f.attrs, "deprecated"),
+				secret: has_flag(&f.attrs, "secret"),
+				flatten: options(&f.attrs, "serde")
+					.iter()
+					.any(|n| matches!(n, syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("flatten"))),
+			})
+			.collect(),
+	)
+}
+
+/// Generates `template_fields` describing each field, fields whose
+/// type is a config themselves become tables while flattened ones add
+/// the keys of their type in place
+fn template_fields(fields: &[Field]) -> String {
+	let field = |f: &Field| {
+		if f.flatten {
+			return format!(
+				"(&configr::__private::Probe::<{}>(std::marker::PhantomData)).section_fields().\
+				 unwrap_or_default()",
+				f.ty.to_token_stream()
+			);
+		}
+		format!("vec![{}]", template_field(f))
+	};
+	// Only flattened fields need the lists to be joined
+	let fields = if fields.iter().any(|f| f.flatten) {
+		format!(
+			"[{}].concat()",
+			fields.iter().map(field).collect::<Vec<_>>().join(", ")
+		)
+	} else {
+		format!(
+			"vec![{}]",
+			fields.iter().map(template_field).collect::<Vec<_>>().join(", ")
+		)
+	};
 	format!(
-		r#"impl Config<Self> for {} {{
-		fn populate_template(fd: std::fs::File) -> std::io::Result<()> {{
-			use std::io::Write;
-			let mut writer = std::io::BufWriter::new(fd);
-			writer.write(toml::to_string::<Self>(&Default::default()).unwrap().as_bytes())?;
-			writer.flush()?;
-			Ok(())
-		}}
-	}}"#,
-		ident
+		r#"fn template_fields() -> Vec<configr::TemplateField> {{
+            #[allow(unused_imports)]
+            use configr::__private::{{SectionProbe as _, ValueProbe as _}};
+            {}
+        }}"#,
+		fields
 	)
-	.parse()
-	.unwrap()
-}
\ No newline at end of file
+}
+
+/// Generates the `TemplateField` describing `f`
+fn template_field(f: &Field) -> String {
+	let mut builders = String::new();
+	if !f.doc.is_empty() {
+		builders.push_str(&format!(".with_doc(&{})", str_slice(&f.doc)));
+	}
+	if let Some(default) = &f.default {
+		builders.push_str(&format!(".with_default({:?})", default));
+	}
+	if is_option(&f.ty) {
+		builders.push_str(".optional()");
+	}
+	if f.skip {
+		builders.push_str(".skipped()");
+	}
+	builders.push_str(&format!(".with_type({:?})", type_name(&f.ty)));
+	if let Some(example) = &f.example {
+		builders.push_str(&format!(".with_example({:?})", example));
+	}
+	if let Some(env) = &f.env {
+		builders.push_str(&format!(".with_env({:?})", env));
+	}
+	if let Some(note) = &f.deprecated {
+		builders.push_str(&format!(".deprecated({:?})", note));
+	}
+	if f.secret {
+		builders.push_str(".secret()");
+	}
+	if collection(&f.ty) == Some(Collection::Map) {
+		return format!(
+			"configr::TemplateField::table({:?}, Vec::new()).map(){}",
+			f.key, builders
+		);
+	}
+	if let (None, Some(element)) = (&f.default, list_element(&f.ty)) {
+		return format!(
+			"configr::TemplateField::list({:?}, \
+			 (&configr::__private::Probe::<{}>(std::marker::PhantomData)).section_fields()){}",
+			f.key,
+			element.to_token_stream(),
+			builders
+		);
+	}
+	format!(
+		"configr::TemplateField::new({:?}, \
+		 (&configr::__private::Probe::<{}>(std::marker::PhantomData)).section_fields()){}",
+		f.key,
+		f.ty.to_token_stream(),
+		builders
+	)
+}
+
+/// The name of `ty` the way it is written in Rust, without the
+/// spacing token streams put between every token
+fn type_name(ty: &syn::Type) -> String {
+	ty.to_token_stream()
+		.to_string()
+		.replace(" < ", "<")
+		.replace(" <", "<")
+		.replace(" >", ">")
+		.replace(" ,", ",")
+		.replace(" :: ", "::")
+		.replace("& ", "&")
+}
+
+/// Checks whether `ty` is an `Option`, which serde reads as `None`
+/// when the key is missing
+fn is_option(ty: &syn::Type) -> bool { type_ident(ty).map_or(false, |i| i == "Option") }
+
+/// The collection types with their own template scaffolding
+#[derive(PartialEq)]
+enum Collection {
+	/// A `Vec`, written as an empty array or an array of tables
+	List,
+	/// A `HashMap` or `BTreeMap`, written as an empty table
+	Map,
+}
+
+/// Checks whether `ty` is one of the collections templates write
+/// scaffolding for
+fn collection(ty: &syn::Type) -> Option<Collection> {
+	match type_ident(ty)?.to_string().as_str() {
+		"Vec" => Some(Collection::List),
+		"HashMap" | "BTreeMap" => Some(Collection::Map),
+		_ => None,
+	}
+}
+
+/// The element type of `ty` if it is a `Vec`, whose template is an
+/// array of tables if the element is a config
+fn list_element(ty: &syn::Type) -> Option<&syn::Type> {
+	if collection(ty)? != Collection::List {
+		return None;
+	}
+	let segment = match ty {
+		syn::Type::Path(p) => p.path.segments.last()?,
+		_ => return None,
+	};
+	match &segment.arguments {
+		syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+			syn::GenericArgument::Type(ty) => Some(ty),
+			_ => None,
+		}),
+		_ => None,
+	}
+}
+
+/// The name of the type `ty` is a path to, without its module and
+/// generics
+fn type_ident(ty: &syn::Type) -> Option<&syn::Ident> {
+	match ty {
+		syn::Type::Path(p) if p.qself.is_none() => p.path.segments.last().map(|s| &s.ident),
+		_ => None,
+	}
+}
+
+/// Collects the options of every `#[configr(...)]` among the
+/// attributes
+fn configr_options(attrs: &[syn::Attribute]) -> Vec<syn::NestedMeta> { options(attrs, "configr") }
+
+/// Collects the options of every `#[attr(...)]` among the attributes
+fn options(
+	attrs: &[syn::Attribute],
+	attr: &str,
+) -> Vec<syn::NestedMeta> {
+	attrs
+		.iter()
+		.filter(|a| a.path.is_ident(attr))
+		.filter_map(|a| match a.parse_meta() {
+			Ok(syn::Meta::List(l)) => Some(l.nested),
+			_ => None,
+		})
+		.flatten()
+		.collect()
+}
+
+/// Finds the string value of `#[configr(name = "...")]` among the
+/// attributes
+fn attr_value(
+	attrs: &[syn::Attribute],
+	name: &str,
+) -> Option<String> {
+	attr_lit(attrs, name).as_ref().and_then(lit_str)
+}
+
+/// Finds the literal value of `#[configr(name = ...)]` among the
+/// attributes
+fn attr_lit(
+	attrs: &[syn::Attribute],
+	name: &str,
+) -> Option<syn::Lit> {
+	configr_options(attrs).into_iter().find_map(|n| match n {
+		syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident(name) => Some(nv.lit),
+		_ => None,
+	})
+}
+
+/// Finds the name serde deserializes a field from, set by either
+/// `#[serde(rename = "...")]` or `#[serde(rename(deserialize =
+/// "..."))]`
+fn serde_rename(attrs: &[syn::Attribute]) -> Option<String> {
+	options(attrs, "serde").into_iter().find_map(|n| match n {
+		syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("rename") => lit_str(&nv.lit),
+		syn::NestedMeta::Meta(syn::Meta::List(l)) if l.path.is_ident("rename") =>
+			l.nested.into_iter().find_map(|n| match n {
+				syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("deserialize") =>
+					lit_str(&nv.lit),
+				_ => None,
+			}),
+		_ => None,
+	})
+}
+
+/// Collects the lines of the doc comments among the attributes, with
+/// the space following `///` removed
+fn doc_lines(attrs: &[syn::Attribute]) -> Vec<String> {
+	attrs
+		.iter()
+		.filter(|a| a.path.is_ident("doc"))
+		.filter_map(|a| match a.parse_meta() {
+			Ok(syn::Meta::NameValue(nv)) => lit_str(&nv.lit),
+			_ => None,
+		})
+		.flat_map(|doc| {
+			doc.split('\n')
+				.map(|line| line.strip_prefix(' ').unwrap_or(line).trim_end().to_string())
+				.collect::<Vec<_>>()
+		})
+		.collect()
+}
+
+/// Generates a `&[&str]` literal of `lines`, without the leading `&`
+fn str_slice(lines: &[String]) -> String { format!("{:?}", lines) }
+
+fn lit_str(lit: &syn::Lit) -> Option<String> {
+	match lit {
+		syn::Lit::Str(s) => Some(s.value()),
+		_ => None,
+	}
+}
+
+/// Checks for `#[configr(name)]` among the attributes
+fn has_flag(
+	attrs: &[syn::Attribute],
+	name: &str,
+) -> bool {
+	configr_options(attrs)
+		.iter()
+		.any(|n| matches!(n, syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident(name)))
+}
+
+/// Generates the `FORMAT` constant from `#[configr(format = "...")]`,
+/// leaving it at the trait default if the attribute is missing
+fn format_const(attrs: &[syn::Attribute]) -> String {
+	attr_value(attrs, "format")
+		.map(|format| {
+			let mut chars = format.chars();
+			let variant: String = chars
+				.next()
+				.map(|c| c.to_ascii_uppercase())
+				.into_iter()
+				.chain(chars)
+				.collect();
+			format!("const FORMAT: configr::Format = configr::Format::{};", variant)
+		})
+		.unwrap_or_default()
+}
+
+/// Generates `APP_NAME`, `load_default` and `load_default_in_dir` for
+/// the app named by
+/// `#[configr(app = "...")]`, or nothing if the attribute is missing
+fn app_impl(
+	ident: &syn::Ident,
+	generics: &syn::Generics,
+	attrs: &[syn::Attribute],
+) -> String {
+	let (params, ty, where_clause) = impl_generics(ident, generics, &["Self: configr::Config<Self>"], "");
+	attr_value(attrs, "app")
+		.map(|app| {
+			format!(
+				r#"impl{} {} {} {{
+            /// The app name set by `#[configr(app = "...")]`
+            pub const APP_NAME: &'static str = {:?};
+
+            /// Load the config like [`load`](configr::Config::load) for the app
+            /// [`APP_NAME`](Self::APP_NAME)
+            pub fn load_default(force_user_dir: bool) -> std::result::Result<Self, configr::ConfigError> {{
+                <Self as configr::Config<Self>>::load(Self::APP_NAME, force_user_dir)
+            }}
+
+            /// Load the config like [`load_in_dir`](configr::Config::load_in_dir)
+            /// for the app [`APP_NAME`](Self::APP_NAME)
+            pub fn load_default_in_dir(
+                config_dir: &std::path::Path,
+            ) -> std::result::Result<Self, configr::ConfigError> {{
+                <Self as configr::Config<Self>>::load_in_dir(Self::APP_NAME, config_dir)
+            }}
+        }}"#,
+				params, ty, where_clause, app
+			)
+		})
+		.unwrap_or_default()
+}
+
+/// Generates the trait items overridden by the struct's configr
+/// attributes
+fn hooks(attrs: &[syn::Attribute]) -> String {
+	[
+		validate_fn(attrs),
+		version_const(attrs),
+		mode_const(attrs),
+		local_const(attrs),
+		current_dir_const(attrs),
+		file_name_fn(attrs),
+		migrate_fn(attrs),
+		migrations_fn(attrs),
+	]
+	.join("\n")
+}
+
+/// Generates the `LOCAL_APP_DATA` constant from `#[configr(local)]`,
+/// leaving it at the trait default if the attribute is missing
+fn local_const(attrs: &[syn::Attribute]) -> String {
+	if has_flag(attrs, "local") {
+		"const LOCAL_APP_DATA: bool = true;".to_string()
+	} else {
+		String::new()
+	}
+}
+
+/// Generates the `CURRENT_DIR` constant from
+/// `#[configr(current_dir)]`, leaving it at the trait default if the
+/// attribute is missing
+fn current_dir_const(attrs: &[syn::Attribute]) -> String {
+	if has_flag(attrs, "current_dir") {
+		"const CURRENT_DIR: bool = true;".to_string()
+	} else {
+		String::new()
+	}
+}
+
+/// Generates `file_name` from `#[configr(file = "...")]`, leaving it
+/// at the trait default if the attribute is missing
+fn file_name_fn(attrs: &[syn::Attribute]) -> String {
+	attr_value(attrs, "file")
+		.map(|file| format!("fn file_name() -> String {{ {:?}.to_string() }}", file))
+		.unwrap_or_default()
+}
+
+/// Generates the `FILE_MODE` constant from `#[configr(mode =
+/// 0o640)]`, leaving it at the trait default if the attribute is
+/// missing
+fn mode_const(attrs: &[syn::Attribute]) -> String {
+	match attr_lit(attrs, "mode") {
+		Some(syn::Lit::Int(i)) => format!("const FILE_MODE: u32 = {};", i),
+		_ => String::new(),
+	}
+}
+
+/// Generates the `VERSION` constant from `#[configr(version = 2)]`,
+/// leaving it at the trait default if the attribute is missing
+fn version_const(attrs: &[syn::Attribute]) -> String {
+	attr_lit(attrs, "version")
+		.and_then(|lit| match lit {
+			syn::Lit::Int(i) => Some(i.base10_digits().to_string()),
+			syn::Lit::Str(s) => Some(s.value()),
+			_ => None,
+		})
+		.map(|version| format!("const VERSION: u32 = {};", version))
+		.unwrap_or_default()
+}
+
+/// Generates `migrate` calling the function named by
+/// `#[configr(migrate = "...")]`, leaving it at the trait default if
+/// the attribute is missing
+fn migrate_fn(attrs: &[syn::Attribute]) -> String {
+	attr_value(attrs, "migrate")
+		.map(|path| {
+			format!(
+				"fn migrate(raw: configr::toml::Value, from_version: u32) -> configr::toml::Value {{ \
+				 {}(raw, from_version) }}",
+				path
+			)
+		})
+		.unwrap_or_default()
+}
+
+/// Generates `migrations` calling the function named by
+/// `#[configr(migrations = "...")]`, leaving it at the trait default
+/// if the attribute is missing
+fn migrations_fn(attrs: &[syn::Attribute]) -> String {
+	attr_value(attrs, "migrations")
+		.map(|path| {
+			format!(
+				"fn migrations() -> Vec<fn(configr::toml::Value) -> configr::toml::Value> {{ {}() }}",
+				path
+			)
+		})
+		.unwrap_or_default()
+}
+
+/// Generates `validate` calling the function named by
+/// `#[configr(validate = "...")]`, leaving it at the trait default if
+/// the attribute is missing
+fn validate_fn(attrs: &[syn::Attribute]) -> String {
+	attr_value(attrs, "validate")
+		.map(|path| {
+			format!(
+				"fn validate(&self) -> std::result::Result<(), String> {{ {}(self) }}",
+				path
+			)
+		})
+		.unwrap_or_default()
+}
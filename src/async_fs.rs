@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+
+use snafu::ResultExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+	app_dir_name,
+	lock,
+	parse_config,
+	read_error,
+	strip_bom,
+	template_error,
+	Config,
+	CreateFs,
+	NotADirectory,
+	NotAFile,
+	Result,
+	WriteTemplate,
+};
+
+/// Get the location of the config file at
+/// `config_dir/app-name/file_name` like
+/// [`create_config_file`][crate::create_config_file], without
+/// blocking on the directory and file creation
+pub(crate) async fn create_config_file<C>(
+	app_name: &str,
+	file_name: &str,
+	config_dir: &Path,
+) -> Result<PathBuf>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let mut config_location = config_dir.join(app_dir_name(app_name)?);
+	match tokio::fs::metadata(&config_location).await {
+		Ok(metadata) if metadata.is_dir() => {},
+		Ok(_) =>
+			return NotADirectory {
+				path: config_location,
+			}
+			.fail(),
+		Err(_) => tokio::fs::create_dir_all(&config_location)
+			.await
+			.context(CreateFs {
+				path: &config_location,
+			})?,
+	}
+	config_location.push(file_name);
+	let mut options = tokio::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	options.mode(C::FILE_MODE);
+	match options.open(&config_location).await {
+		Ok(fd) => {
+			let mut template = Vec::new();
+			C::populate_template(&mut template).map_err(|e| template_error(e, &config_location))?;
+			let fd = fd.into_std().await;
+			let fd = blocking(move || lock::exclusive(&fd).map(|()| fd))
+				.await
+				.context(CreateFs {
+					path: &config_location,
+				})?;
+			let mut fd = tokio::fs::File::from_std(fd);
+			// tokio only guarantees the write finished once it is flushed
+			let written = match fd.write_all(&template).await {
+				Ok(()) => fd.flush().await,
+				Err(e) => Err(e),
+			};
+			written.context(WriteTemplate {
+				path: &config_location,
+			})?;
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {},
+		Err(source) =>
+			return Err(source).context(CreateFs {
+				path: &config_location,
+			}),
+	}
+
+	Ok(config_location)
+}
+
+/// Read and parse the config file at `path` like
+/// [`read_config`][crate::read_config], without blocking on the read
+/// or on the shared lock taken with the `lock` feature
+pub(crate) async fn read_config<C>(path: &Path) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	if tokio::fs::metadata(path).await.map_or(false, |m| m.is_dir()) {
+		return NotAFile { path }.fail();
+	}
+	let owned = path.to_path_buf();
+	let content = blocking(move || {
+		let mut file = std::fs::File::open(&owned)?;
+		lock::shared(&file)?;
+		let mut content = String::new();
+		std::io::Read::read_to_string(&mut file, &mut content)?;
+		Ok(content)
+	})
+	.await
+	.map_err(|e| read_error(e, path))?;
+
+	parse_config(C::FORMAT, &strip_bom(content), path)
+}
+
+/// Run `f` on tokio's blocking threads, as waiting for the advisory
+/// locks of [`lock`] would otherwise block the runtime
+async fn blocking<T, F>(f: F) -> std::io::Result<T>
+where
+	F: FnOnce() -> std::io::Result<T> + Send + 'static,
+	T: Send + 'static,
+{
+	tokio::task::spawn_blocking(f)
+		.await
+		.unwrap_or_else(|e| Err(std::io::Error::new(std::io::ErrorKind::Other, e)))
+}
@@ -0,0 +1,43 @@
+use miette::{Diagnostic, LabeledSpan, SourceCode};
+
+use crate::ConfigError;
+
+/// Shows parse errors as a snippet of the config file, with the
+/// position the error points at highlighted
+impl Diagnostic for ConfigError {
+	fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+		match self {
+			ConfigError::Deserialize { .. } => Some(Box::new("configr::deserialize")),
+			_ => None,
+		}
+	}
+
+	fn source_code(&self) -> Option<&dyn SourceCode> {
+		match self {
+			ConfigError::Deserialize { toml, .. } => Some(toml),
+			_ => None,
+		}
+	}
+
+	fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+		match self {
+			ConfigError::Deserialize { source, toml, .. } => {
+				let (line, col) = source.line_col()?;
+				let span = LabeledSpan::new(Some(source.to_string()), byte_offset(toml, line, col), 1);
+				Some(Box::new(std::iter::once(span)))
+			},
+			_ => None,
+		}
+	}
+}
+
+/// The byte offset of the zero based `line` and `col` in `content`,
+/// clamped to its end
+fn byte_offset(
+	content: &str,
+	line: usize,
+	col: usize,
+) -> usize {
+	let start: usize = content.split_inclusive('\n').take(line).map(str::len).sum();
+	(start + col).min(content.len())
+}
@@ -0,0 +1,47 @@
+use std::path::{Path, PathBuf};
+
+use crate::{read_file, ConfigError, Result};
+
+/// The variables of the `.env` file in `app_dir`, or in the current
+/// directory if there is none there, which is empty if neither exists
+pub(crate) fn vars(app_dir: &Path) -> Result<Vec<(String, String)>> {
+	for path in &[app_dir.join(".env"), PathBuf::from(".env")] {
+		match read_file(path) {
+			Ok(content) => {
+				event!(debug, "Read environment overrides from {}", path.display());
+				return Ok(parse(&content));
+			},
+			Err(ConfigError::NotFound { .. }) => {},
+			Err(error) => return Err(error),
+		}
+	}
+
+	Ok(Vec::new())
+}
+
+/// Parse the `KEY=value` lines of a `.env` file, skipping blank
+/// lines, comments and lines without a `=`\
+/// Keys may be preceded by `export`, and values may be wrapped in
+/// single or double quotes, which are removed.
+fn parse(content: &str) -> Vec<(String, String)> {
+	content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| {
+			let line = line.strip_prefix("export ").unwrap_or(line);
+			let (key, value) = line.split_at(line.find('=')?);
+			Some((key.trim().to_string(), unquote(value[1..].trim()).to_string()))
+		})
+		.collect()
+}
+
+/// Remove one pair of matching single or double quotes around `value`
+fn unquote(value: &str) -> &str {
+	for quote in &['"', '\''] {
+		if value.len() >= 2 && value.starts_with(*quote) && value.ends_with(*quote) {
+			return &value[1..value.len() - 1];
+		}
+	}
+	value
+}
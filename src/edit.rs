@@ -0,0 +1,100 @@
+use toml_edit::{Document, Item, Table};
+
+/// Update the TOML document `content` to hold `value`, keeping its
+/// comments, whitespace and key order, or `None` if `content` isn't
+/// valid TOML or `value` can't be written into it
+///
+/// Keys with unchanged values are left untouched, changed ones are
+/// replaced in place keeping the comments around them, and keys
+/// missing from `value` are removed. Lines end the way they did in
+/// `content`, rather than new keys ending with `\n` in a file using
+/// `\r\n`.
+pub(crate) fn update_document(
+	content: &str,
+	value: &toml::Value,
+) -> Option<String> {
+	let mut document = content.parse::<Document>().ok()?;
+	update_table(document.as_table_mut(), value.as_table()?)?;
+
+	Some(with_line_ending(&document.to_string(), line_ending(content)))
+}
+
+/// The line ending `content` uses, which is `\r\n` if its first line
+/// ends with one and `\n` otherwise
+pub(crate) fn line_ending(content: &str) -> &'static str {
+	match content.find('\n') {
+		Some(i) if content[..i].ends_with('\r') => "\r\n",
+		_ => "\n",
+	}
+}
+
+/// Replace the line endings of `content` with `ending`
+fn with_line_ending(
+	content: &str,
+	ending: &str,
+) -> String {
+	let content = content.replace("\r\n", "\n");
+	if ending == "\n" {
+		content
+	} else {
+		content.replace('\n', ending)
+	}
+}
+
+fn update_table(
+	table: &mut Table,
+	new: &toml::value::Table,
+) -> Option<()> {
+	let removed: Vec<String> = table
+		.iter()
+		.map(|(key, _)| key.to_string())
+		.filter(|key| !new.contains_key(key))
+		.collect();
+	for key in removed {
+		table.remove(&key);
+	}
+	for (key, value) in new {
+		let item = &mut table[key.as_str()];
+		match (item, value) {
+			(Item::Table(existing), toml::Value::Table(new)) => update_table(existing, new)?,
+			// Anything else is turned into a table, as `toml` can only write
+			// tables as whole documents
+			(item, toml::Value::Table(new)) => {
+				let mut table = Table::new();
+				update_table(&mut table, new)?;
+				*item = Item::Table(table);
+			},
+			(item, value) => update_value(item, value)?,
+		}
+	}
+
+	Some(())
+}
+
+fn update_value(
+	item: &mut Item,
+	value: &toml::Value,
+) -> Option<()> {
+	if let Item::Value(existing) = item {
+		// The existing value is displayed along with its trailing
+		// comment, which is still valid TOML
+		let unchanged = toml::from_str::<toml::Value>(&format!("v = {}", existing))
+			.ok()
+			.and_then(|t| t.get("v").cloned())
+			.map_or(false, |v| &v == value);
+		if unchanged {
+			return Some(());
+		}
+	}
+	let new = value.to_string().parse::<toml_edit::Value>().ok()?;
+	*item = match item {
+		Item::Value(existing) => Item::Value(toml_edit::decorated(
+			new,
+			existing.decor().prefix(),
+			existing.decor().suffix(),
+		)),
+		_ => Item::Value(new),
+	};
+
+	Some(())
+}
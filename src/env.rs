@@ -0,0 +1,216 @@
+use crate::TemplateField;
+
+/// Override the keys of `value` with the environment variables named
+/// `{PREFIX}_{KEY}`, where both the prefix and the key are uppercased
+/// and `__` separates the keys of nested tables, so
+/// `MYAPP_DATABASE__PORT` sets `database.port`\
+/// `{PREFIX}_CONFIG` is skipped, as it conventionally names the
+/// config file for `load_with_env_override` rather than a key.
+pub(crate) fn apply_env(
+	value: &mut toml::Value,
+	prefix: &str,
+) {
+	apply_vars(value, prefix, vars());
+}
+
+/// The environment variables, skipping the ones that aren't valid
+/// unicode as they can't name a key, instead of panicking like
+/// `std::env::vars` would
+fn vars() -> impl Iterator<Item = (String, String)> {
+	std::env::vars_os().filter_map(|(n, v)| Some((n.into_string().ok()?, v.into_string().ok()?)))
+}
+
+/// The keys named by the variables in `vars` starting with `PREFIX_`,
+/// split into their path of nested tables, along with their values,
+/// skipping the reserved `PREFIX_CONFIG`
+fn prefixed<I>(
+	prefix: &str,
+	vars: I,
+) -> impl Iterator<Item = (Vec<String>, String)>
+where
+	I: IntoIterator<Item = (String, String)>,
+{
+	let prefix = format!("{}_", prefix.to_ascii_uppercase());
+	vars.into_iter().filter_map(move |(name, raw)| {
+		let path: Vec<String> = name
+			.strip_prefix(&prefix)
+			.filter(|&key| key != "CONFIG")?
+			.split("__")
+			.map(str::to_ascii_lowercase)
+			.collect();
+		Some((path, raw)).filter(|(path, _)| !path.iter().any(String::is_empty))
+	})
+}
+
+/// A table of the environment variables named `{PREFIX}_{KEY}`,
+/// nested the same way as [`apply_env`]\
+/// The variables of fields with a `String` type in `fields` stay
+/// strings, any other is read as a TOML value.
+pub(crate) fn env_table(
+	prefix: &str,
+	fields: &[TemplateField],
+) -> toml::Value {
+	let mut table = toml::value::Table::new();
+	for (path, raw) in prefixed(prefix, vars()) {
+		let parsed = match field_at(fields, &path).and_then(|f| f.type_name.as_deref()) {
+			Some("String") | Some("Option<String>") => toml::Value::String(raw),
+			_ => parse_scalar(&raw),
+		};
+		set_path(&mut table, &path, |_| parsed);
+	}
+
+	toml::Value::Table(table)
+}
+
+/// The field at `path` in `fields`, looking into the fields of tables
+fn field_at<'a>(
+	fields: &'a [TemplateField],
+	path: &[String],
+) -> Option<&'a TemplateField> {
+	let (key, rest) = path.split_first()?;
+	let field = fields.iter().find(|f| &f.key == key)?;
+	if rest.is_empty() {
+		return Some(field);
+	}
+
+	field_at(field.fields.as_deref()?, rest)
+}
+
+/// The dotted paths of the required keys in `fields` which are
+/// missing from `value`, looking into tables even if they are missing
+/// themselves\
+/// Optional and skipped keys aren't required, and neither are the
+/// keys of maps and arrays of tables.
+pub(crate) fn missing_keys(
+	value: &toml::Value,
+	fields: &[TemplateField],
+) -> Vec<String> {
+	let empty = toml::Value::Table(toml::value::Table::new());
+	let mut missing = Vec::new();
+	for field in fields.iter().filter(|f| !f.optional && !f.skipped) {
+		let nested = value.get(&field.key);
+		match (&field.fields, nested) {
+			(Some(fields), _) if !field.map && !field.array => missing.extend(
+				missing_keys(nested.unwrap_or(&empty), fields)
+					.into_iter()
+					.map(|key| format!("{}.{}", field.key, key)),
+			),
+			(_, None) => missing.push(field.key.clone()),
+			_ => {},
+		}
+	}
+
+	missing
+}
+
+/// The environment variables setting the dotted `keys`, joined for
+/// an error message
+pub(crate) fn var_names(
+	prefix: &str,
+	keys: &[String],
+) -> String {
+	keys.iter()
+		.map(|key| format!("{}_{}", prefix, key.replace('.', "__")).to_ascii_uppercase())
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Override the keys of `value` with the variables in `vars` the
+/// same way [`apply_env`] does with the environment
+pub(crate) fn apply_vars<I>(
+	value: &mut toml::Value,
+	prefix: &str,
+	vars: I,
+) where
+	I: IntoIterator<Item = (String, String)>,
+{
+	let table = match value.as_table_mut() {
+		Some(table) => table,
+		None => return,
+	};
+	for (path, raw) in prefixed(prefix, vars) {
+		set_path(table, &path, |existing| parse_env_value(&raw, existing));
+	}
+}
+
+/// Set the key at `path` in `table` to the value made by `value` from
+/// the existing one, creating the tables along the way and replacing
+/// any other value in the way
+fn set_path(
+	table: &mut toml::value::Table,
+	path: &[String],
+	value: impl FnOnce(Option<&toml::Value>) -> toml::Value,
+) {
+	let (key, rest) = match path.split_first() {
+		Some(split) => split,
+		None => return,
+	};
+	if rest.is_empty() {
+		let parsed = value(table.get(key));
+		table.insert(key.clone(), parsed);
+		return;
+	}
+	let nested = table
+		.entry(key.clone())
+		.or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+	if !nested.is_table() {
+		*nested = toml::Value::Table(toml::value::Table::new());
+	}
+	if let Some(nested) = nested.as_table_mut() {
+		set_path(nested, rest, value);
+	}
+}
+
+/// Override the keys of `value` whose field names an environment
+/// variable with `#[configr(env = "...")]`, if that variable is set,
+/// looking into the tables of fields which are tables themselves
+pub(crate) fn apply_field_env(
+	value: &mut toml::Value,
+	fields: &[TemplateField],
+) {
+	let table = match value.as_table_mut() {
+		Some(table) => table,
+		None => return,
+	};
+	for field in fields {
+		if let (Some(nested), Some(value)) = (&field.fields, table.get_mut(&field.key)) {
+			apply_field_env(value, nested);
+		}
+		let raw = match field.env.as_deref().and_then(std::env::var_os) {
+			Some(raw) => raw,
+			None => continue,
+		};
+		if let Ok(raw) = raw.into_string() {
+			// The type is known here, so a string field stays a string even
+			// when the key is missing from the file.
+			let parsed = match field.type_name.as_deref() {
+				Some("String") | Some("Option<String>") => toml::Value::String(raw),
+				_ => parse_env_value(&raw, table.get(&field.key)),
+			};
+			table.insert(field.key.clone(), parsed);
+		}
+	}
+}
+
+/// Parse the value of an environment variable, keeping it as a string
+/// if the key it overrides is a string, and otherwise reading it as a
+/// TOML value
+fn parse_env_value(
+	raw: &str,
+	existing: Option<&toml::Value>,
+) -> toml::Value {
+	match existing {
+		Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+		_ => parse_scalar(raw),
+	}
+}
+
+/// Read `raw` as a TOML value, falling back to a string if it isn't
+/// valid TOML
+fn parse_scalar(raw: &str) -> toml::Value {
+	format!("value = {}", raw)
+		.parse::<toml::Value>()
+		.ok()
+		.and_then(|mut document| document.as_table_mut()?.remove("value"))
+		.unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+use std::io::Write;
+
+use snafu::ResultExt;
+
+use crate::template::{self, TemplateField};
+
+/// The file formats a config can be stored in
+///
+/// TOML is always available, every other format is behind a feature
+/// flag of the same name.
+///
+/// Other formats are picked per type with `#[configr(format =
+/// "json")]` or by overriding [`FORMAT`][crate::Config::FORMAT], so
+/// enabling a feature never moves an existing config to another file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// [TOML](https://toml.io), stored in `config.toml`
+	Toml,
+	/// [JSON](https://www.json.org), stored in `config.json`
+	#[cfg(feature = "json")]
+	Json,
+	/// [YAML](https://yaml.org), stored in `config.yaml`
+	#[cfg(feature = "yaml")]
+	Yaml,
+	/// [RON](https://github.com/ron-rs/ron), stored in `config.ron`
+	#[cfg(feature = "ron")]
+	Ron,
+}
+
+/// Every recognized file extension along with its format, in the
+/// order [`load_auto`][crate::Config::load_auto] looks for them
+pub(crate) const EXTENSIONS: &[(&str, Format)] = &[
+	("toml", Format::Toml),
+	#[cfg(feature = "json")]
+	("json", Format::Json),
+	#[cfg(feature = "yaml")]
+	("yaml", Format::Yaml),
+	#[cfg(feature = "yaml")]
+	("yml", Format::Yaml),
+	#[cfg(feature = "ron")]
+	("ron", Format::Ron),
+];
+
+/// Errors from serializing or deserializing one of the supported
+/// formats
+#[derive(snafu::Snafu, Debug)]
+#[non_exhaustive]
+pub enum FormatError {
+	/// TOML parsing failed in some way.
+	#[snafu(display("{}", source))]
+	TomlDe { source: toml::de::Error },
+	/// TOML serialization failed in some way.
+	#[snafu(display("{}", source))]
+	TomlSer { source: toml::ser::Error },
+	/// JSON parsing or serialization failed in some way.
+	#[cfg(feature = "json")]
+	#[snafu(display("{}", source))]
+	Json { source: serde_json::Error },
+	/// YAML parsing or serialization failed in some way.
+	#[cfg(feature = "yaml")]
+	#[snafu(display("{}", source))]
+	Yaml { source: serde_yaml::Error },
+	/// RON parsing or serialization failed in some way.
+	#[cfg(feature = "ron")]
+	#[snafu(display("{}", source))]
+	Ron { source: ron::Error },
+}
+
+impl Format {
+	/// The format used by types that don't pick one themselves, which
+	/// is TOML whichever format features are enabled
+	pub const DEFAULT: Format = Format::Toml;
+
+	/// The file extension used for this format, without the leading
+	/// dot
+	pub fn extension(self) -> &'static str {
+		match self {
+			Format::Toml => "toml",
+			#[cfg(feature = "json")]
+			Format::Json => "json",
+			#[cfg(feature = "yaml")]
+			Format::Yaml => "yaml",
+			#[cfg(feature = "ron")]
+			Format::Ron => "ron",
+		}
+	}
+
+	/// Find the format a file with the given extension is stored in,
+	/// if that format is enabled
+	pub fn from_extension(extension: &str) -> Option<Format> {
+		EXTENSIONS
+			.iter()
+			.find(|(e, _)| e.eq_ignore_ascii_case(extension))
+			.map(|&(_, format)| format)
+	}
+
+	/// The name of the config file for this format, e.g.
+	/// `config.toml`
+	pub fn file_name(self) -> String { format!("config.{}", self.extension()) }
+
+	/// Deserialize `content` into `C`
+	pub fn deserialize<C>(
+		self,
+		content: &str,
+	) -> Result<C, FormatError>
+	where
+		C: serde::de::DeserializeOwned,
+	{
+		match self {
+			Format::Toml => toml::from_str(content).context(TomlDe),
+			#[cfg(feature = "json")]
+			Format::Json => serde_json::from_str(content).context(Json),
+			#[cfg(feature = "yaml")]
+			Format::Yaml => serde_yaml::from_str(content).context(Yaml),
+			#[cfg(feature = "ron")]
+			Format::Ron => ron::de::from_str(content).context(Ron),
+		}
+	}
+
+	/// Deserialize `content` into a [`toml::Value`], leaving out any
+	/// null values as TOML has no way to represent them
+	pub(crate) fn deserialize_value(
+		self,
+		content: &str,
+	) -> Result<toml::Value, FormatError> {
+		match self {
+			Format::Toml => toml::from_str(content).context(TomlDe),
+			#[cfg(feature = "json")]
+			Format::Json => {
+				let mut value = serde_json::from_str::<serde_json::Value>(content).context(Json)?;
+				strip_json_nulls(&mut value);
+				toml::Value::try_from(value).context(TomlSer)
+			},
+			#[cfg(feature = "yaml")]
+			Format::Yaml => {
+				let mut value = serde_yaml::from_str::<serde_yaml::Value>(content).context(Yaml)?;
+				strip_yaml_nulls(&mut value);
+				toml::Value::try_from(value).context(TomlSer)
+			},
+			#[cfg(feature = "ron")]
+			Format::Ron => {
+				let value = ron::de::from_str::<ron::Value>(content).context(Ron)?;
+				Ok(ron_to_toml(value).unwrap_or_else(|| toml::Value::Table(toml::value::Table::new())))
+			},
+		}
+	}
+
+	/// Serialize `value` into a string in this format
+	pub fn serialize<T>(
+		self,
+		value: &T,
+	) -> Result<String, FormatError>
+	where
+		T: serde::Serialize + ?Sized,
+	{
+		match self {
+			Format::Toml => toml::to_string(value).context(TomlSer),
+			#[cfg(feature = "json")]
+			Format::Json => serde_json::to_string_pretty(value).context(Json),
+			#[cfg(feature = "yaml")]
+			Format::Yaml => serde_yaml::to_string(value).context(Yaml),
+			#[cfg(feature = "ron")]
+			Format::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::new()).context(Ron),
+		}
+	}
+
+	/// Write a template containing `fields`, which is what the
+	/// [`Configr`][crate::Configr] derive populates new config files
+	/// with\
+	/// `doc` is written as a comment at the top of the template, as
+	/// are the docs of each field above its key, if the format
+	/// supports comments.
+	pub fn write_template<W>(
+		self,
+		mut writer: W,
+		doc: &[&str],
+		fields: &[TemplateField],
+	) -> std::io::Result<()>
+	where
+		W: Write,
+	{
+		writer.write_all(template::render(self, doc, fields).as_bytes())?;
+		writer.flush()
+	}
+
+	/// Add `doc` and the docs of `fields` as comments to `content`,
+	/// which was serialized in this format, the same way
+	/// [`write_template`][Format::write_template] does\
+	/// Formats without comments return `content` unchanged.
+	pub fn annotate(
+		self,
+		content: &str,
+		doc: &[&str],
+		fields: &[TemplateField],
+	) -> String {
+		template::annotate(self, content, doc, fields)
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		match self {
+			Format::Toml => f.write_str("TOML"),
+			#[cfg(feature = "json")]
+			Format::Json => f.write_str("JSON"),
+			#[cfg(feature = "yaml")]
+			Format::Yaml => f.write_str("YAML"),
+			#[cfg(feature = "ron")]
+			Format::Ron => f.write_str("RON"),
+		}
+	}
+}
+
+/// Lets template code use `?` on (de)serialization, failing with
+/// [`InvalidData`][std::io::ErrorKind::InvalidData]
+impl From<FormatError> for std::io::Error {
+	fn from(error: FormatError) -> Self { std::io::Error::new(std::io::ErrorKind::InvalidData, error) }
+}
+
+impl FormatError {
+	/// The zero based line and column the error points at in the
+	/// parsed content, if it has one
+	pub fn line_col(&self) -> Option<(usize, usize)> {
+		match self {
+			FormatError::TomlDe { source } => source.line_col(),
+			FormatError::TomlSer { .. } => None,
+			#[cfg(feature = "json")]
+			FormatError::Json { source } if source.line() > 0 =>
+				Some((source.line() - 1, source.column().saturating_sub(1))),
+			#[cfg(feature = "json")]
+			FormatError::Json { .. } => None,
+			#[cfg(feature = "yaml")]
+			FormatError::Yaml { source } => source
+				.location()
+				.map(|l| (l.line().saturating_sub(1), l.column().saturating_sub(1))),
+			#[cfg(feature = "ron")]
+			FormatError::Ron { source } if source.position.line > 0 =>
+				Some((source.position.line - 1, source.position.col.saturating_sub(1))),
+			#[cfg(feature = "ron")]
+			FormatError::Ron { .. } => None,
+		}
+	}
+
+	/// The same kind of error with `message` instead of the original
+	/// one, which loses the position the error points at
+	pub(crate) fn with_message(
+		&self,
+		message: &str,
+	) -> FormatError {
+		match self {
+			FormatError::TomlDe { .. } => FormatError::TomlDe {
+				source: serde::de::Error::custom(message),
+			},
+			FormatError::TomlSer { .. } => FormatError::TomlSer {
+				source: serde::ser::Error::custom(message),
+			},
+			#[cfg(feature = "json")]
+			FormatError::Json { .. } => FormatError::Json {
+				source: serde::de::Error::custom(message),
+			},
+			#[cfg(feature = "yaml")]
+			FormatError::Yaml { .. } => FormatError::Yaml {
+				source: serde::de::Error::custom(message),
+			},
+			#[cfg(feature = "ron")]
+			FormatError::Ron { .. } => FormatError::Ron {
+				source: serde::de::Error::custom(message),
+			},
+		}
+	}
+
+	/// The format whose (de)serialization failed
+	pub fn format(&self) -> Format {
+		match self {
+			FormatError::TomlDe { .. } | FormatError::TomlSer { .. } => Format::Toml,
+			#[cfg(feature = "json")]
+			FormatError::Json { .. } => Format::Json,
+			#[cfg(feature = "yaml")]
+			FormatError::Yaml { .. } => Format::Yaml,
+			#[cfg(feature = "ron")]
+			FormatError::Ron { .. } => Format::Ron,
+		}
+	}
+}
+
+/// Remove every null value from the objects in `value`
+#[cfg(feature = "json")]
+fn strip_json_nulls(value: &mut serde_json::Value) {
+	match value {
+		serde_json::Value::Object(map) =>
+			*map = std::mem::take(map)
+				.into_iter()
+				.filter(|(_, v)| !v.is_null())
+				.map(|(k, mut v)| {
+					strip_json_nulls(&mut v);
+					(k, v)
+				})
+				.collect(),
+		serde_json::Value::Array(values) => values.iter_mut().for_each(strip_json_nulls),
+		_ => {},
+	}
+}
+
+/// Remove every null value from the mappings in `value`
+#[cfg(feature = "yaml")]
+fn strip_yaml_nulls(value: &mut serde_yaml::Value) {
+	match value {
+		serde_yaml::Value::Mapping(map) =>
+			*map = std::mem::replace(map, serde_yaml::Mapping::new())
+				.into_iter()
+				.filter(|(_, v)| !v.is_null())
+				.map(|(k, mut v)| {
+					strip_yaml_nulls(&mut v);
+					(k, v)
+				})
+				.collect(),
+		serde_yaml::Value::Sequence(values) => values.iter_mut().for_each(strip_yaml_nulls),
+		_ => {},
+	}
+}
+
+/// Convert a RON `value` into a [`toml::Value`], leaving out `None`
+/// and unit values as TOML has no way to represent them, along with
+/// map entries whose key isn't a string
+#[cfg(feature = "ron")]
+fn ron_to_toml(value: ron::Value) -> Option<toml::Value> {
+	Some(match value {
+		ron::Value::Bool(b) => toml::Value::Boolean(b),
+		ron::Value::Char(c) => toml::Value::String(c.to_string()),
+		ron::Value::String(s) => toml::Value::String(s),
+		ron::Value::Number(n) => match n.as_i64() {
+			Some(i) => toml::Value::Integer(i),
+			None => toml::Value::Float(n.as_f64()?),
+		},
+		ron::Value::Option(value) => return value.and_then(|v| ron_to_toml(*v)),
+		ron::Value::Unit => return None,
+		ron::Value::Seq(values) => toml::Value::Array(values.into_iter().filter_map(ron_to_toml).collect()),
+		ron::Value::Map(map) => toml::Value::Table(
+			map.iter()
+				.filter_map(|(k, v)| match k {
+					ron::Value::String(k) => Some((k.clone(), ron_to_toml(v.clone())?)),
+					_ => None,
+				})
+				.collect(),
+		),
+	})
+}
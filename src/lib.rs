@@ -1,27 +1,166 @@
-use std::fs::{create_dir_all, File};
-use std::path::PathBuf;
+use std::convert::TryFrom;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "watch")]
+use std::sync::mpsc::{channel, Receiver};
+use std::time::SystemTime;
 
 /// Reexport of Attribute Macros
 pub use configr_derive::Configr;
+/// Deprecated alias of [`Configr`] with `#[configr(default)]`
 pub use configr_derive::ConfigrDefault;
-use snafu::{OptionExt, ResultExt};
+use snafu::{IntoError, OptionExt, ResultExt};
+/// Reexport of the toml crate, whose [`Value`][toml::Value] is used
+/// for working with config files of any format
+pub use toml;
+#[cfg(feature = "schemars")]
+extern crate dep_schemars as schemars;
+
+// Declared first so its macros are available to the other modules
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "tokio")] mod async_fs;
+#[cfg(feature = "miette")] mod diagnostic;
+#[cfg(feature = "dotenv")] mod dotenv;
+mod edit;
+mod env;
+mod format;
+mod loader;
+mod lock;
+#[cfg(feature = "figment")] mod provider;
+mod shared;
+mod template;
+mod units;
+#[cfg(feature = "watch")] mod watch;
+
+pub use format::{Format, FormatError};
+pub use loader::ConfigLoader;
+#[cfg(feature = "figment")]
+pub use provider::ConfigProvider;
+pub use shared::SharedConfig;
+pub use template::TemplateField;
+pub use units::{ByteSize, HumanDuration, UnitError};
+#[cfg(feature = "watch")] pub use watch::ConfigWatcher;
 
 /// List of error categories
+///
+/// New variants may be added in minor releases, so matches on it need
+/// a wildcard arm.
 #[derive(snafu::Snafu, Debug)]
+#[non_exhaustive]
 pub enum ConfigError {
-	/// Loading the config.toml file failed.
+	/// Loading the config file failed.
 	#[snafu(display("Unable to read configuration file from {}: {}", path.display(), source))]
 	ReadConfig { source: std::io::Error, path: PathBuf },
+	/// The config file doesn't exist.
+	#[snafu(display("Configuration file {} does not exist", path.display()))]
+	NotFound { path: PathBuf },
+	/// The app-name directory the config file belongs in is a file.
+	#[snafu(display(
+		"Unable to use {} as the configuration directory, it exists but is not a directory",
+		path.display()
+	))]
+	NotADirectory { path: PathBuf },
+	/// The config file is a directory.
+	#[snafu(display(
+		"Unable to use {} as the configuration file, it exists but is a directory",
+		path.display()
+	))]
+	NotAFile { path: PathBuf },
+	/// The config file to be created already exists.
+	#[snafu(display("Configuration file {} already exists", path.display()))]
+	AlreadyExists { path: PathBuf },
+	/// The config file has no table named `section`, which
+	/// [`load_section`][Config::load_section] reads.
+	#[snafu(display("Configuration file {} has no [{}] section", path.display(), section))]
+	MissingSection { section: String, path: PathBuf },
+	/// Required keys of a config read by
+	/// [`from_env`][Config::from_env] have no environment variable
+	/// setting them, `keys` holds their dotted paths.
+	#[snafu(display(
+		"Missing the environment variables {} for required configuration keys",
+		env::var_names(prefix, keys)
+	))]
+	MissingEnv { prefix: String, keys: Vec<String> },
+	/// Reading the config file isn't allowed.
+	#[snafu(display("Permission denied reading configuration file {}", path.display()))]
+	PermissionDenied { path: PathBuf },
+	/// Reading the config from a stream failed.
+	#[snafu(display("Unable to read configuration from stream: {}", source))]
+	ReadStream { source: std::io::Error },
 	/// Creating the directory or file failed.
 	#[snafu(display("Unable to create configuration file or directory {}: {}", path.display(), source))]
 	CreateFs { source: std::io::Error, path: PathBuf },
-	/// TOML parsing failed in some way.
-	#[snafu(display("Unable to parse TOML\n{}\n```\n{}```{}", path.display(), toml, source))]
+	/// Writing the template to a newly created config file failed.
+	#[snafu(display("Unable to write configuration template to {}: {}", path.display(), source))]
+	WriteTemplate { source: std::io::Error, path: PathBuf },
+	/// Writing a template failed, returned by
+	/// [`populate_template`][Config::populate_template] which doesn't
+	/// know where it is written to.\
+	/// Loading turns it into [`ConfigError::WriteTemplate`] with the
+	/// path of the config file.
+	#[snafu(display("Unable to write configuration template: {}", source))]
+	Template { source: std::io::Error },
+	/// Serializing a template failed, returned by
+	/// [`populate_template`][Config::populate_template] which doesn't
+	/// know where it is written to.\
+	/// Loading turns it into [`ConfigError::Serialize`] with the path
+	/// of the config file.
+	#[snafu(display("Unable to serialize configuration template to {}: {}", source.format(), source))]
+	TemplateSerialize { source: FormatError },
+	/// Writing the config file failed.
+	#[snafu(display("Unable to write configuration file to {}: {}", path.display(), source))]
+	WriteConfig { source: std::io::Error, path: PathBuf },
+	/// Removing the config file failed.
+	#[snafu(display("Unable to delete configuration file {}: {}", path.display(), source))]
+	DeleteConfig { source: std::io::Error, path: PathBuf },
+	/// Merging two configs failed, because one of them couldn't be
+	/// converted to or from a [`toml::Value`].
+	#[snafu(display("Unable to merge configurations: {}", source))]
+	Merge { source: FormatError },
+	/// Comparing a config with its default failed, because one of
+	/// them couldn't be converted to a [`toml::Value`].
+	#[snafu(display("Unable to compare configuration with its default: {}", source))]
+	Diff { source: FormatError },
+	/// Dumping a config failed, because it couldn't be serialized as
+	/// TOML.
+	#[snafu(display("Unable to serialize configuration for dumping: {}", source))]
+	Dump { source: FormatError },
+	/// Writing a dumped config to its writer failed.
+	#[snafu(display("Unable to write configuration dump: {}", source))]
+	WriteDump { source: std::io::Error },
+	/// Parsing the config file failed in some way, `toml` holds the
+	/// raw contents of the file regardless of its format, with the
+	/// values of fields marked with `#[configr(secret)]` replaced by
+	/// `"***"`, as they are in the error message.\
+	/// As the contents may contain secrets, and may be too large for
+	/// logs, only the line the error points at is displayed, cut to
+	/// [`EXCERPT_WIDTH`][ConfigError::EXCERPT_WIDTH] characters. The
+	/// full contents are available from `toml` or
+	/// [`content`][ConfigError::content].
+	#[snafu(display(
+		"Unable to parse {}\n{}\n{}{}",
+		source.format(),
+		path.display(),
+		error_excerpt(toml, source, 0, ConfigError::EXCERPT_WIDTH),
+		source
+	))]
 	Deserialize {
-		source: toml::de::Error,
+		source: FormatError,
 		path: PathBuf,
 		toml: String,
 	},
+	/// Serialization of the config failed in some way.
+	#[snafu(display(
+		"Unable to serialize configuration to {} for {}: {}",
+		source.format(),
+		path.display(),
+		source
+	))]
+	Serialize { source: FormatError, path: PathBuf },
 	/// Unable to get the configuration directory, possibly because of
 	/// an unsupported OS.
 	#[snafu(display(
@@ -29,17 +168,144 @@ pub enum ConfigError {
 		 the `dirs` crate"
 	))]
 	ConfigDir,
+	/// The config was parsed but rejected by its
+	/// [`validate`][Config::validate] hook.
+	#[snafu(display("Invalid configuration: {}", message))]
+	Validation { message: String },
+	/// None of the directories searched by
+	/// [`load_with_dirs`][Config::load_with_dirs] contained a valid
+	/// config file, `paths` holds every file that was tried.
+	#[snafu(display("No valid configuration file found, tried {}", display_paths(paths)))]
+	NoValidConfig { paths: Vec<PathBuf> },
+	/// The config file contains keys the config doesn't have, which
+	/// [`load_strict`][Config::load_strict] rejects.
+	#[snafu(display("Unknown keys in configuration file {}: {}", path.display(), keys.join(", ")))]
+	UnknownKeys { keys: Vec<String>, path: PathBuf },
+	/// The app name is empty or would escape the config directory, as
+	/// it contains a path separator or `..`.
+	#[snafu(display(
+		"Invalid app name {:?}, it must not be empty or contain `/`, `\\` or `..`",
+		app_name
+	))]
+	InvalidAppName { app_name: String },
+	/// The profile name is empty or would escape the app directory,
+	/// as it contains a path separator or `..`.
+	#[snafu(display(
+		"Invalid profile name {:?}, it must not be empty or contain `/`, `\\` or `..`",
+		profile
+	))]
+	InvalidProfile { profile: String },
+	/// The config file was written for a newer version of the config
+	/// than [`Config::VERSION`], which can't be migrated back.
+	#[snafu(display(
+		"Configuration file {} is for version {} of the config, newer than the supported version {}",
+		path.display(),
+		version,
+		supported
+	))]
+	NewerVersion {
+		path: PathBuf,
+		version: u32,
+		supported: u32,
+	},
+	/// Watching the config file for changes failed.
+	#[cfg(feature = "watch")]
+	#[snafu(display("Unable to watch configuration file {}: {}", path.display(), source))]
+	Watch { source: notify::Error, path: PathBuf },
 }
 
 type Result<T, E = ConfigError> = std::result::Result<T, E>;
 
+impl ConfigError {
+	/// The number of characters of the line an error points at shown
+	/// in the message of [`ConfigError::Deserialize`]
+	pub const EXCERPT_WIDTH: usize = 120;
+
+	/// The raw contents of the config file that failed to parse, if
+	/// this is a [`ConfigError::Deserialize`], with the values of
+	/// secret fields redacted
+	pub fn content(&self) -> Option<&str> {
+		match self {
+			ConfigError::Deserialize { toml, .. } => Some(toml),
+			_ => None,
+		}
+	}
+
+	/// The lines of the config file around the position a
+	/// [`ConfigError::Deserialize`] points at, with a caret under its
+	/// column, or `None` if it has no position\
+	/// Up to `context` lines are shown before and after that line,
+	/// and each line is cut to `width` characters around the column,
+	/// with `...` marking the parts left out.
+	pub fn excerpt(
+		&self,
+		context: usize,
+		width: usize,
+	) -> Option<String> {
+		match self {
+			ConfigError::Deserialize { source, toml, .. } =>
+				Some(error_excerpt(toml, source, context, width)).filter(|e| !e.is_empty()),
+			_ => None,
+		}
+	}
+}
+
+/// The stand-in path in the errors of
+/// [`load_from_str`][Config::load_from_str]
+pub const STR_PATH: &str = "<string>";
+
+/// The stand-in path in the errors of
+/// [`load_from_reader`][Config::load_from_reader]
+pub const READER_PATH: &str = "<reader>";
+
+/// The stand-in path in the errors of [`from_env`][Config::from_env]
+pub const ENV_PATH: &str = "<environment>";
+
+/// The system configuration directory searched after
+/// `$XDG_CONFIG_DIRS` on UNIX systems, which is /etc unless the
+/// `CONFIGR_SYSCONFDIR` environment variable is set when building,
+/// such as to /usr/local/etc by packagers using another prefix
+pub const SYSTEM_CONFIG_DIR: &str = match option_env!("CONFIGR_SYSCONFDIR") {
+	Some(dir) => dir,
+	None => "/etc",
+};
+
+/// Which config file wins when both a system and a user config file
+/// exist, see [`load_with_precedence`][Config::load_with_precedence]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Precedence {
+	/// The first system config file that loads is used, and the user
+	/// one only when none does, which is what [`load`][Config::load]
+	/// does.
+	SystemFirst,
+	/// The user config file is used whenever it exists, and the
+	/// system ones only when it doesn't.
+	UserFirst,
+	/// The user config file is merged over the system ones key by
+	/// key, like [`load_merged`][Config::load_merged] does.
+	Merge,
+}
+
+impl Default for Precedence {
+	fn default() -> Self { Precedence::UserFirst }
+}
+
+/// The future returned by the async loaders
+///
+/// Trait methods can't return `impl Future`, so the loaders box their
+/// future instead, which is `Send` for use with multi-threaded
+/// runtimes.
+#[cfg(feature = "tokio")]
+pub type LoadFuture<'a, C> = std::pin::Pin<Box<dyn std::future::Future<Output = Result<C>> + Send + 'a>>;
+
 /// This is the main trait that you implement on your struct, either
 /// manually or using the [`Configr`][configr_derive::Configr]
 /// attribute macro
 ///
 /// ```no_run
-/// use configr::{Config, ConfigrDefault};
-/// #[derive(ConfigrDefault, Default, serde::Serialize, serde::Deserialize)]
+/// use configr::{Config, Configr};
+/// #[derive(Configr, Default, serde::Serialize, serde::Deserialize)]
+/// #[configr(default)]
 /// pub struct BotConfig {
 ///     bot_username: String,
 ///     client_id: String,
@@ -53,30 +319,100 @@ pub trait Config<C>
 where
 	C: serde::de::DeserializeOwned + Config<C>,
 {
+	/// The format the config file is stored in, which also decides
+	/// the extension of the config file\
+	/// Defaults to [`Format::DEFAULT`], the derive macros can set it
+	/// with `#[configr(format = "json")]`
+	const FORMAT: Format = Format::DEFAULT;
+
+	/// The current version of the config's schema, which is compared
+	/// to the `version` key of the config file to decide which
+	/// [`migrate`][Self::migrate] steps to run\
+	/// Defaults to 0, where no migrations run, the derive macros can
+	/// set it with `#[configr(version = 2)]`
+	///
+	/// # Notes
+	/// A file without a `version` key is treated as version 0, so the
+	/// config should have a `version` field for new files to record
+	/// the version they were written with. Loading a file with a
+	/// newer version fails with [`ConfigError::NewerVersion`].
+	const VERSION: u32 = 0;
+
+	/// The UNIX permissions config files are created with, as they
+	/// often contain secrets\
+	/// Defaults to `0o600`, only letting the owner read and write the
+	/// file, the derive macros can set it with
+	/// `#[configr(mode = 0o640)]`
+	///
+	/// # Notes
+	/// This only applies when the file is created, existing files
+	/// keep their permissions, and is ignored on other systems.
+	const FILE_MODE: u32 = 0o600;
+
+	/// Whether the user configuration directory is `%LOCALAPPDATA%`
+	/// instead of the roaming `%APPDATA%` on Windows, for configs
+	/// that shouldn't follow the user to other machines, such as ones
+	/// with absolute local paths\
+	/// Defaults to `false`, the derive macros can set it with
+	/// `#[configr(local)]`. This has no effect on other systems.
+	const LOCAL_APP_DATA: bool = false;
+
+	/// Whether the current directory is searched as the system
+	/// configuration directory on systems without one, which are the
+	/// ones other than UNIX\
+	/// Defaults to `false` so loading doesn't create files in
+	/// whatever directory the app is started from, the derive macros
+	/// can set it with `#[configr(current_dir)]`.
+	const CURRENT_DIR: bool = false;
+
+	/// The name of the config file in the app directory, used by
+	/// every function that doesn't take a file name\
+	/// Defaults to the [`FORMAT`][Self::FORMAT]'s
+	/// [`file_name`][Format::file_name] such as `config.toml`, the
+	/// derive macros can set it with
+	/// `#[configr(file = "settings.toml")]`
+	fn file_name() -> String { Self::FORMAT.file_name() }
+
+	/// A [`ConfigLoader`] for loading the config with more options
+	/// than [`load`][Self::load] takes, such as the file name or an
+	/// environment variable prefix
+	fn loader() -> ConfigLoader<C> { ConfigLoader::new() }
+
 	/// Load the config from the config file located in the OS
 	/// specific config directory\
 	/// This is a wrapper around
-	/// [`load_with_dir`][Self::load_with_dir], which just
+	/// [`load_in_dir`][Self::load_in_dir], which just
 	/// takes the system configuration directory, instead of a custom
 	/// path.\
-	/// Read [`load_with_dir`][Self::load_with_dir] for more
+	/// Read [`load_in_dir`][Self::load_in_dir] for more
 	/// informationg about failure and config folder structure
 	///
 	/// # Failures
 	/// this will contains the same failure possibilities as
-	/// [`load_with_dir`][Self::load_with_dir] in addtion this can
+	/// [`load_in_dir`][Self::load_in_dir] in addtion this can
 	/// also fail due to the user configuration path not being found,
 	/// if this is the case, you should switch to using
-	/// `load_with_dir` with a custom path
+	/// `load_in_dir` with a custom path
+	///
+	/// If no config file could be found in the end this fails with
+	/// [`ConfigError::NoValidConfig`] listing every path that was
+	/// searched, see [`search_paths`][Self::search_paths].
 	///
 	/// # Notes
 	/// This should in almost every case be prefered over supplying
 	/// your own configuration directory.
 	///
+	/// A system config file takes precedence over the user one here,
+	/// use [`load_with_precedence`][Self::load_with_precedence] with
+	/// the default [`Precedence::UserFirst`] to let the user config
+	/// win instead.
+	///
 	/// The `force_user_dir` option makes sure the fuction always
-	/// prefers the user configuration path, compared to using /etc on
-	/// UNIX systems and besides the executable on other systems, if
-	/// the user configuration file is not found
+	/// prefers the user configuration path, compared to using
+	/// `$XDG_CONFIG_DIRS` and /etc on UNIX systems and the current
+	/// directory on other systems if
+	/// [`CURRENT_DIR`][Self::CURRENT_DIR] is set, if the user
+	/// configuration file is not found
 	///
 	/// The configuration directory is as follows\
 	/// Linux: `$XDG_CONFIG_HOME/`\
@@ -86,18 +422,204 @@ where
 		app_name: &str,
 		force_user_dir: bool,
 	) -> Result<C> {
+		let config = in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_in_dir(app_name, dir)
+		});
+
+		with_search_paths::<C, _>(config, app_name, force_user_dir)
+	}
+
+	/// Load the config from the OS specific config directories, with
+	/// `precedence` deciding which file wins when both a system and a
+	/// user config file exist\
+	/// This is a wrapper around
+	/// [`load_with_precedence_in_dirs`][Self::
+	/// load_with_precedence_in_dirs], with the system configuration
+	/// directories listed by [`search_paths`][Self::search_paths] and
+	/// the user configuration directory.
+	///
+	/// # Failures
+	/// this will contain the same failure possibilities as
+	/// [`load_with_precedence_in_dirs`][Self::
+	/// load_with_precedence_in_dirs] in addition to failing if the
+	/// user configuration path could not be found
+	fn load_with_precedence(
+		app_name: &str,
+		precedence: Precedence,
+	) -> Result<C> {
+		if precedence == Precedence::SystemFirst {
+			return Self::load(app_name, false);
+		}
+
+		Self::load_with_precedence_in_dirs(
+			app_name,
+			precedence,
+			&system_config_dirs(C::CURRENT_DIR),
+			&user_config_dir(C::LOCAL_APP_DATA)?,
+		)
+	}
+
+	/// Load the config from the system configuration directories
+	/// `system_dirs`, in the order they are tried, and the user
+	/// configuration directory `user_dir`, with `precedence` deciding
+	/// which file wins when both exist
+	///
+	/// # Notes
+	/// With [`Precedence::UserFirst`] the system config files are
+	/// only read when the user one doesn't exist, and the first of
+	/// them that exists is used. When none of the files exist the
+	/// template is created in `user_dir`, whatever the precedence.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir] for the file that is used,
+	/// or [`load_merged_in_dirs`][Self::load_merged_in_dirs] for
+	/// [`Precedence::Merge`]
+	fn load_with_precedence_in_dirs(
+		app_name: &str,
+		precedence: Precedence,
+		system_dirs: &[PathBuf],
+		user_dir: &Path,
+	) -> Result<C> {
+		match precedence {
+			Precedence::SystemFirst => in_dirs(
+				system_dirs,
+				|| Ok(user_dir.to_path_buf()),
+				|dir| Self::load_in_dir(app_name, dir),
+			),
+			Precedence::UserFirst => {
+				let file_name = C::file_name();
+				if !config_location(user_dir, app_name, &file_name)?.exists() {
+					for dir in system_dirs {
+						if config_location(dir, app_name, &file_name)?.exists() {
+							return Self::load_in_dir(app_name, dir);
+						}
+					}
+				}
+				Self::load_in_dir(app_name, user_dir)
+			},
+			Precedence::Merge => {
+				let mut dirs: Vec<PathBuf> = system_dirs.iter().rev().cloned().collect();
+				dirs.push(user_dir.to_path_buf());
+				Self::load_merged_in_dirs(app_name, &dirs)
+			},
+		}
+	}
+
+	/// The config files [`load`][Self::load] looks for, in the order
+	/// it tries them, without reading or creating anything
+	///
+	/// # Notes
+	/// Unless `force_user_dir` is set this starts with the files in
+	/// each of `$XDG_CONFIG_DIRS` (/etc/xdg if unset) and then /etc
+	/// on UNIX systems and besides the executable on other systems,
+	/// followed by the file in the user configuration directory.
+	///
+	/// # Failures
+	/// This can only fail due to the user configuration path not
+	/// being found, or an invalid app name
+	fn search_paths(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<Vec<PathBuf>> {
+		let file_name = C::file_name();
+		let mut paths = Vec::new();
 		if !force_user_dir {
-			if let Ok(c) = if cfg!(target_family = "unix") {
-				Self::load_with_dir(app_name, &mut PathBuf::from("/etc"))
-			} else {
-				Self::load_with_dir(app_name, &mut PathBuf::from("./"))
-			} {
-				return Ok(c);
+			for dir in system_config_dirs(C::CURRENT_DIR) {
+				paths.push(config_location(&dir, app_name, &file_name)?);
 			}
 		}
-		let mut dir = dirs::config_dir().context(ConfigDir)?;
+		paths.push(config_location(
+			&user_config_dir(C::LOCAL_APP_DATA)?,
+			app_name,
+			&file_name,
+		)?);
+
+		Ok(paths)
+	}
+
+	/// Load the config from the config file in the platform's
+	/// directory for the project identified by `qualifier`,
+	/// `organization` and `application`, following the conventions
+	/// of [`directories::ProjectDirs`]
+	///
+	/// # Notes
+	/// For the qualifier `com`, organization `Example` and
+	/// application `My App` the config file is located at\
+	/// Linux: `$XDG_CONFIG_HOME/myapp/config.toml`\
+	/// Windows: `%APPDATA%/Example/My App/config/config.toml`\
+	/// Mac OS: `$HOME/Library/Application
+	/// Support/com.Example.My-App/config.toml`
+	///
+	/// Like [`load_in_dir`][Self::load_in_dir] the file is created
+	/// from the template if it doesn't exist.
+	///
+	/// # Failures
+	/// this will contain the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir] in addition to failing if
+	/// no home directory could be found for the project directories
+	fn load_project(
+		qualifier: &str,
+		organization: &str,
+		application: &str,
+	) -> Result<C> {
+		let project =
+			directories::ProjectDirs::from(qualifier, organization, application).context(ConfigDir)?;
+		let config_location =
+			create_config_file_in::<C>(project.config_dir().to_path_buf(), &C::file_name())?;
+
+		read_config(C::FORMAT, &config_location)
+	}
+
+	/// Load the config from exactly the file at `path`, such as one
+	/// passed with a `--config` flag, without any directory
+	/// resolution
+	///
+	/// # Notes
+	/// The file is parsed according to its extension if it is one of
+	/// the enabled formats, and otherwise as the config's
+	/// [`FORMAT`][Self::FORMAT]. It is never created if it doesn't
+	/// exist.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the file doesn't exist, with [`ConfigError::NotFound`]\
+	/// * If the file could not be read properly\
+	/// * If the file is not valid data in its format
+	fn load_from_path(path: impl AsRef<Path>) -> Result<C> {
+		let path = path.as_ref();
+		let format = path
+			.extension()
+			.and_then(|e| e.to_str())
+			.and_then(Format::from_extension)
+			.unwrap_or(C::FORMAT);
 
-		Self::load_with_dir(app_name, &mut dir)
+		read_config(format, path)
+	}
+
+	/// Load the config from the file named by the environment
+	/// variable `env_var` if it is set, such as `MYAPP_CONFIG`, and
+	/// otherwise the same way as [`load`][Self::load]
+	///
+	/// # Notes
+	/// The variable holds the full path of the config file, which is
+	/// read as is without any directory resolution, and is never
+	/// created if it doesn't exist.
+	///
+	/// # Failures
+	/// If the variable is set this fails with
+	/// [`ConfigError::NotFound`] if the file doesn't exist, and
+	/// otherwise has the same failure possibilities as
+	/// [`load`][Self::load]
+	fn load_with_env_override(
+		app_name: &str,
+		env_var: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		match std::env::var_os(env_var) {
+			Some(path) => Self::load_from_path(path),
+			None => Self::load(app_name, force_user_dir),
+		}
 	}
 
 	/// Load the config from the config file located in the app
@@ -110,118 +632,5047 @@ where
 	///
 	/// The app_name will be converted to lowercase-kebab-case
 	///
+	/// The file extension follows the config's
+	/// [`FORMAT`][Self::FORMAT], so a JSON config is read from
+	/// `config_dir/app-name/config.json`
+	///
 	/// # Failures
 	/// This function will Error under the following circumstances\
 	/// * If the config.toml or the app-name directory could not be
 	///   created\
+	/// * If the template could not be written to the newly created
+	///   config.toml\
 	/// * If the config.toml could not be read properly\
-	/// * If the config.toml is not valid toml data
-	fn load_with_dir(
+	/// * If the config.toml is not valid data in the config's
+	///   [`FORMAT`][Self::FORMAT]
+	fn load_in_dir(
 		app_name: &str,
-		config_dir: &mut PathBuf,
+		config_dir: &Path,
 	) -> Result<C> {
-		// Get the location of the config file, create directories and the
-		// file itself if needed.
-		let config_location = {
-			config_dir.push(app_name.replace(" ", "-").to_ascii_lowercase());
-			if !config_dir.exists() {
-				create_dir_all(&config_dir).context(CreateFs { path: &config_dir })?;
-			}
-			config_dir.push("config.toml");
-			if !config_dir.exists() {
-				let fd = File::create(&config_dir).context(CreateFs { path: &config_dir })?;
-				C::populate_template(fd).unwrap();
-			}
-			config_dir
-		};
+		Self::load_with_file_name_in_dir(app_name, &C::file_name(), config_dir)
+	}
 
-		let toml_content = std::fs::read_to_string(&config_location).context(ReadConfig {
-			path: &config_location,
-		})?;
+	/// Load the config from the config file located in the OS
+	/// specific config directory like [`load`][Self::load], along
+	/// with whether the file was just created from the template\
+	/// This lets a CLI tell users to edit a freshly created config on
+	/// its first run, without checking whether the file exists
+	/// separately.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load`][Self::load]
+	fn load_report(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(C, bool)> {
+		let report = in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_report_in_dir(app_name, dir)
+		});
 
-		toml::from_str::<C>(&toml_content).context(Deserialize {
-			path: &config_location,
-			toml: &toml_content,
-		})
+		with_search_paths::<C, _>(report, app_name, force_user_dir)
 	}
 
-	fn populate_template(fd: File) -> std::io::Result<()>;
-}
+	/// Load the config from the config file located in the app
+	/// specific config directory like
+	/// [`load_in_dir`][Self::load_in_dir], along with whether the
+	/// file was just created from the template
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_report_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(C, bool)> {
+		let (config_location, created) =
+			create_config_file_reported::<C>(config_dir.join(app_dir_name(app_name)?), &C::file_name())?;
 
-#[cfg(test)]
-mod configr_tests {
-	use configr::{Config, ConfigError, Configr, ConfigrDefault};
-	use serde::{Deserialize, Serialize};
+		Ok((read_config(C::FORMAT, &config_location)?, created))
+	}
 
-	use crate as configr;
+	/// Load the config from the config file located in the OS
+	/// specific config directory like [`load`][Self::load], along
+	/// with a warning for every deprecated key the file still uses\
+	/// This is a wrapper around
+	/// [`load_with_warnings_in_dir`][Self::
+	/// load_with_warnings_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_warnings(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(C, Vec<String>)> {
+		let loaded = in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_warnings_in_dir(app_name, dir)
+		});
 
-	#[derive(ConfigrDefault, Deserialize, Serialize, Debug, Default, PartialEq)]
-	struct TestDefaultConfig {
-		a: String,
-		b: String,
+		with_search_paths::<C, _>(loaded, app_name, force_user_dir)
 	}
 
-	#[derive(Configr, Deserialize, Debug, PartialEq)]
-	struct TestConfig {
-		a: String,
-		b: String,
+	/// Load the config from the config file located in the app
+	/// specific config directory like
+	/// [`load_in_dir`][Self::load_in_dir], along with a warning for
+	/// every key present in the file whose field is marked with
+	/// `#[configr(deprecated = "...")]`
+	///
+	/// # Notes
+	/// Deprecated keys are still read, so old files keep loading
+	/// while users are told to update them. With the `tracing`
+	/// feature every warning is logged as well.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_warnings_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(C, Vec<String>)> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+		let config = parse_config(C::FORMAT, &content, &config_location)?;
+		let warnings = parse_value::<C>(C::FORMAT, &content, &config_location)
+			.map(|value| template::deprecated_keys(&value, &C::template_fields()))
+			.unwrap_or_default();
+		for warning in &warnings {
+			event!(warn, "{} in {}", warning, config_location.display());
+		}
+
+		Ok((config, warnings))
 	}
 
-	#[test]
-	fn generate_template_and_error() {
-		let config = TestConfig::load_with_dir("Test Config1", &mut std::path::PathBuf::from("."));
-		// expect toml parse error with correct fields but no actual values
-		assert!(if let Err(e) = config {
-			if let ConfigError::Deserialize {
-				path,
-				toml,
-				source: _,
-			} = e
-			{
-				if path == std::path::PathBuf::from("./test-config1/config.toml") && toml == "a=\nb=\n" {
-					true
-				} else {
-					false
+	/// Load the config like [`load`][Self::load], using [`tokio::fs`]
+	/// so the runtime isn't blocked on the file I/O
+	///
+	/// # Notes
+	/// The returned [`LoadFuture`] is boxed, as trait methods can't
+	/// return `impl Future`. With the `lock` feature the file is
+	/// locked the same way as by [`load`][Self::load], waiting for
+	/// the lock on tokio's blocking threads.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load`][Self::load]
+	#[cfg(feature = "tokio")]
+	fn load_async<'a>(
+		app_name: &'a str,
+		force_user_dir: bool,
+	) -> LoadFuture<'a, C>
+	where
+		Self: 'a,
+		C: Send + 'a,
+	{
+		Box::pin(async move {
+			if !force_user_dir {
+				for dir in system_config_dirs(C::CURRENT_DIR) {
+					if let Ok(config) = Self::load_in_dir_async(app_name, &dir).await {
+						return Ok(config);
+					}
 				}
-			} else {
-				false
 			}
-		} else {
-			false
-		});
+			let dir = user_config_dir(C::LOCAL_APP_DATA)?;
 
-		std::fs::remove_dir_all("test-config1").unwrap();
+			Self::load_in_dir_async(app_name, &dir).await
+		})
 	}
 
-	#[test]
-	fn read_proper_config() {
-		std::fs::create_dir("test-config2").unwrap();
-		std::fs::write("test-config2/config.toml", b"a=\"test\"\nb=\"test\"\n").unwrap();
-		let config = TestConfig::load_with_dir("Test Config2", &mut std::path::PathBuf::from("."));
-		// expect toml parse error with correct fields but no actual values
-		assert!(if let Ok(c) = config {
-			c == TestConfig {
-				a: "test".into(),
-				b: "test".into(),
-			}
-		} else {
-			false
-		});
+	/// Load the config like [`load_in_dir`][Self::load_in_dir], using
+	/// [`tokio::fs`] so the runtime isn't blocked on the file I/O
+	///
+	/// # Notes
+	/// This is named after [`load_in_dir`][Self::load_in_dir], which
+	/// replaced `load_with_dir`, and like
+	/// [`load_async`][Self::load_async] returns a boxed
+	/// [`LoadFuture`] and takes the same locks as the blocking
+	/// loaders.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	#[cfg(feature = "tokio")]
+	fn load_in_dir_async<'a>(
+		app_name: &'a str,
+		config_dir: &'a Path,
+	) -> LoadFuture<'a, C>
+	where
+		Self: 'a,
+		C: Send + 'a,
+	{
+		Box::pin(async move {
+			let config_location =
+				async_fs::create_config_file::<C>(app_name, &C::file_name(), config_dir).await?;
 
-		std::fs::remove_dir_all("test-config2").unwrap();
+			async_fs::read_config(&config_location).await
+		})
 	}
 
-	#[test]
-	fn default_serialized_config() {
-		let config = TestDefaultConfig::load_with_dir("Test Config3", &mut std::path::PathBuf::from("."));
-		assert!(if let Ok(c) = config {
-			c == TestDefaultConfig {
-				a: Default::default(),
-				b: Default::default(),
-			}
-		} else {
-			false
-		});
-		std::fs::remove_dir_all("test-config3").unwrap();
+	/// Load the config from `contents`, without touching the
+	/// filesystem\
+	/// Useful for tests, or for configs compiled into the binary with
+	/// `include_str!`
+	///
+	/// # Notes
+	/// `contents` is parsed as the config's [`FORMAT`][Self::FORMAT],
+	/// with missing keys filled in from [`defaults`][Self::defaults]
+	/// the same way as a config file.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::Deserialize`] if `contents` is
+	/// not valid, its `path` is [`STR_PATH`] as there is no file
+	fn load_from_str(contents: &str) -> Result<C> {
+		let contents = contents.strip_prefix(BOM).unwrap_or(contents);
+
+		parse_config(C::FORMAT, contents, Path::new(STR_PATH))
+	}
+
+	/// Load the config by reading `reader` to the end, such as stdin
+	/// or a socket\
+	/// The contents are parsed the same way as
+	/// [`load_from_str`][Self::load_from_str]
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If `reader` could not be read, or its contents are not valid
+	///   UTF-8\
+	/// * If the contents are not valid data in the config's
+	///   [`FORMAT`][Self::FORMAT], in which case the `path` of the
+	///   error is [`READER_PATH`]
+	fn load_from_reader(mut reader: impl std::io::Read) -> Result<C> {
+		let mut contents = String::new();
+		reader.read_to_string(&mut contents).context(ReadStream)?;
+
+		parse_config(C::FORMAT, &strip_bom(contents), Path::new(READER_PATH))
+	}
+
+	/// Load the config from a config file named `file_name` in the OS
+	/// specific config directory, instead of the default config.toml\
+	/// This is a wrapper around
+	/// [`load_with_file_name_in_dir`][Self::
+	/// load_with_file_name_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_file_name(
+		app_name: &str,
+		file_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_file_name_in_dir(app_name, file_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located at
+	/// `config_dir/app-name/file_name`, creating it from the template
+	/// if it doesn't exist
+	///
+	/// # Notes
+	/// The file is always parsed as the config's
+	/// [`FORMAT`][Self::FORMAT], regardless of the extension in
+	/// `file_name`.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_file_name_in_dir(
+		app_name: &str,
+		file_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, file_name, config_dir)?;
+
+		read_config(C::FORMAT, &config_location)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, overriding its values with
+	/// environment variables\
+	/// This is a wrapper around
+	/// [`load_with_env_in_dir`][Self::load_with_env_in_dir], which
+	/// resolves the directory the same way [`load`][Self::load] does.
+	fn load_with_env(
+		app_name: &str,
+		prefix: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_env_in_dir(app_name, prefix, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, overriding its values with
+	/// environment variables
+	///
+	/// # Notes
+	/// Every key in the file can be overriden by an environment
+	/// variable named `{PREFIX}_{KEY}`, both uppercased, so with the
+	/// prefix `myapp` the variable `MYAPP_CLIENT_ID` overrides
+	/// `client_id`. A key doesn't need to be present in the file to
+	/// be set from the environment.
+	///
+	/// Keys of nested tables are separated by a double underscore, so
+	/// `MYAPP_DATABASE__PORT` sets `port` in the `database` table,
+	/// which is created if it is missing. A single underscore is part
+	/// of the key, which is why keys that themselves contain a double
+	/// underscore can't be set from the environment.
+	///
+	/// If the file already contains a string for the key, the
+	/// variable is used as a string verbatim. Otherwise its value is
+	/// read as a TOML value, so `8080` becomes an integer and `true`
+	/// a boolean, falling back to a string if it isn't valid TOML.
+	///
+	/// Fields naming their own variable with `#[configr(env =
+	/// "...")]` are overridden by it afterwards, see
+	/// [`load_with_field_env_in_dir`][Self::
+	/// load_with_field_env_in_dir].
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_env_in_dir(
+		app_name: &str,
+		prefix: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+
+		read_config_with_env(&config_location, Some(prefix))
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, overriding its values with the
+	/// variables of a `.env` file and the environment\
+	/// This is a wrapper around
+	/// [`load_with_dotenv_in_dir`][Self::load_with_dotenv_in_dir],
+	/// which resolves the directory the same way [`load`][Self::load]
+	/// does.
+	#[cfg(feature = "dotenv")]
+	fn load_with_dotenv(
+		app_name: &str,
+		prefix: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_dotenv_in_dir(app_name, prefix, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory like
+	/// [`load_with_env_in_dir`][Self::load_with_env_in_dir], first
+	/// applying the `KEY=value` lines of a `.env` file as if they
+	/// were environment variables
+	///
+	/// # Notes
+	/// The `.env` file is read from the app-name directory next to
+	/// the config file, or from the current directory if there is
+	/// none there. A missing `.env` file is not an error.
+	///
+	/// Variables set in the environment take precedence over the same
+	/// variables in the `.env` file, which is never written back to
+	/// the environment itself.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], in addition to failing if
+	/// the `.env` file exists but could not be read
+	#[cfg(feature = "dotenv")]
+	fn load_with_dotenv_in_dir(
+		app_name: &str,
+		prefix: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let vars = dotenv::vars(config_location.parent().unwrap_or(config_dir))?;
+
+		read_config_with_vars(&config_location, Some(prefix), vars)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, overriding the fields that name an
+	/// environment variable\
+	/// This is a wrapper around
+	/// [`load_with_field_env_in_dir`][Self::
+	/// load_with_field_env_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_field_env(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_field_env_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, overriding the fields that name an
+	/// environment variable with `#[configr(env = "DATABASE_URL")]`
+	///
+	/// # Notes
+	/// Only the named variables are read, unlike the prefix of
+	/// [`load_with_env_in_dir`][Self::load_with_env_in_dir] which
+	/// matches every variable starting with it. A variable that isn't
+	/// set leaves the value from the file alone, and values are
+	/// parsed the same way, except that a `String` field is always
+	/// set to the variable verbatim.
+	///
+	/// Fields in nested tables can name variables too, as long as the
+	/// table is present in the file.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_field_env_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+
+		read_config_with_env(&config_location, None)
+	}
+
+	/// Read the config from the environment alone, without any file,
+	/// such as in containers which configure everything through
+	/// environment variables
+	///
+	/// # Notes
+	/// The variables are named `{PREFIX}_{KEY}` like for
+	/// [`load_with_env`][Self::load_with_env], with `__` separating
+	/// the keys of nested tables. Variables of `String` fields are
+	/// always strings, others are read as TOML values. The config's
+	/// [`defaults`][Self::defaults] fill in the keys without a
+	/// variable, and fields naming a variable with `#[configr(env =
+	/// "...")]` are read as well.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::MissingEnv`] listing every
+	/// required key without a variable, and otherwise with
+	/// [`ConfigError::Deserialize`] pointing at [`ENV_PATH`] if the
+	/// values can't be deserialized, or
+	/// [`ConfigError::Validation`] if they aren't valid
+	fn from_env(prefix: &str) -> Result<C> {
+		let fields = C::template_fields();
+		let mut value = with_defaults::<C>(env::env_table(prefix, &fields));
+		env::apply_field_env(&mut value, &fields);
+		let content = toml::to_string(&value).unwrap_or_default();
+
+		from_value(value.clone(), Path::new(ENV_PATH), &content).map_err(|error| {
+			let keys = env::missing_keys(&value, &fields);
+			match error {
+				ConfigError::Deserialize { .. } if !keys.is_empty() => ConfigError::MissingEnv {
+					prefix: prefix.to_string(),
+					keys,
+				},
+				error => error,
+			}
+		})
+	}
+
+	/// Load the config from the first of `dirs` containing a valid
+	/// config file, trying them in order
+	///
+	/// # Notes
+	/// Each directory is searched for `dir/app-name/config.toml`, so
+	/// earlier directories take precedence over later ones. Unlike
+	/// [`load_in_dir`][Self::load_in_dir] no template is created, a
+	/// directory without the file is skipped, as is one whose file
+	/// can't be read or parsed.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::NoValidConfig`], listing every
+	/// path tried, if none of the directories contain a valid config
+	fn load_with_dirs(
+		app_name: &str,
+		dirs: &[PathBuf],
+	) -> Result<C> {
+		let file_name = C::file_name();
+		let mut paths = Vec::with_capacity(dirs.len());
+		for dir in dirs {
+			let config_location = config_location(dir, app_name, &file_name)?;
+			if config_location.exists() {
+				if let Ok(config) = read_config(C::FORMAT, &config_location) {
+					return Ok(config);
+				}
+			}
+			paths.push(config_location);
+		}
+
+		NoValidConfig { paths }.fail()
+	}
+
+	/// Load the config by merging the user config file over the
+	/// system one, so the system config can provide defaults which
+	/// users override key by key\
+	/// This is a wrapper around
+	/// [`load_merged_in_dirs`][Self::load_merged_in_dirs], with the
+	/// system configuration directories, `$XDG_CONFIG_DIRS` (/etc/xdg
+	/// if unset) and /etc on UNIX systems and besides the executable
+	/// on other systems, followed by the user configuration
+	/// directory. Earlier entries of `$XDG_CONFIG_DIRS` take
+	/// precedence over later ones, as the XDG Base Directory
+	/// specification says.
+	///
+	/// # Failures
+	/// this will contain the same failure possibilities as
+	/// [`load_merged_in_dirs`][Self::load_merged_in_dirs] in addition
+	/// to failing if the user configuration path could not be found
+	fn load_merged(app_name: &str) -> Result<C> {
+		let user_dir = user_config_dir(C::LOCAL_APP_DATA)?;
+
+		let mut dirs: Vec<PathBuf> = system_config_dirs(C::CURRENT_DIR).into_iter().rev().collect();
+		dirs.push(user_dir);
+
+		Self::load_merged_in_dirs(app_name, &dirs)
+	}
+
+	/// Load the config by deep merging the config files in each of
+	/// `dirs`, with the files in later directories overriding keys
+	/// from earlier ones
+	///
+	/// # Notes
+	/// Tables are merged key by key, while any other value replaces
+	/// the one from the earlier file. Directories without the config
+	/// file are skipped, and if none of them have it this falls back
+	/// to [`load_in_dir`][Self::load_in_dir] in the last directory,
+	/// creating the template there.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], for any of the files, with
+	/// errors from the merged config pointing at the last file read
+	fn load_merged_in_dirs(
+		app_name: &str,
+		dirs: &[PathBuf],
+	) -> Result<C> {
+		let file_name = C::file_name();
+		let mut merged: Option<(toml::Value, PathBuf, String)> = None;
+		for dir in dirs {
+			let config_location = config_location(dir, app_name, &file_name)?;
+			if !config_location.exists() {
+				continue;
+			}
+			let content = read_file(&config_location)?;
+			let value = parse_value::<C>(C::FORMAT, &content, &config_location)?;
+			merged = Some(match merged {
+				Some((mut base, ..)) => {
+					merge_values(&mut base, value);
+					(base, config_location, content)
+				},
+				None => (value, config_location, content),
+			});
+		}
+
+		match merged {
+			Some((value, path, content)) =>
+				from_value(with_defaults::<C>(migrated::<C>(value, &path)?), &path, &content),
+			None => match dirs.last() {
+				Some(dir) => Self::load_in_dir(app_name, dir),
+				None => NoValidConfig { paths: Vec::new() }.fail(),
+			},
+		}
+	}
+
+	/// Load the config from the OS specific config directory with the
+	/// file merged over `defaults`, such as ones derived from command
+	/// line arguments or computed at runtime\
+	/// This is a wrapper around
+	/// [`load_with_defaults_in_dir`][Self::
+	/// load_with_defaults_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_defaults(
+		app_name: &str,
+		defaults: toml::Value,
+		force_user_dir: bool,
+	) -> Result<C> {
+		let config = in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_defaults_in_dir(app_name, dir, defaults.clone())
+		});
+
+		with_search_paths::<C, _>(config, app_name, force_user_dir)
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, deep merging the file over
+	/// `defaults` so keys missing from the file fall back to them
+	///
+	/// # Notes
+	/// The file is merged the same way as
+	/// [`merge_values`][crate::merge_values] does, after migrating it
+	/// to the current [`VERSION`][Self::VERSION]. The config's own
+	/// [`defaults`][Self::defaults] still apply to keys missing from
+	/// both.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_defaults_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+		defaults: toml::Value,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+		let mut value = defaults;
+		merge_values(
+			&mut value,
+			migrated::<C>(
+				parse_value::<C>(C::FORMAT, &content, &config_location)?,
+				&config_location,
+			)?,
+		);
+
+		from_value(with_defaults::<C>(value), &config_location, &content)
+	}
+
+	/// Load the config for `profile`, such as `dev` or `prod`, from
+	/// the OS specific config directory\
+	/// This is a wrapper around
+	/// [`load_profile_in_dir`][Self::load_profile_in_dir], which
+	/// resolves the directory the same way [`load`][Self::load] does.
+	fn load_profile(
+		app_name: &str,
+		profile: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_profile_in_dir(app_name, profile, dir)
+		})
+	}
+
+	/// Load the config for `profile`, such as `dev` or `prod`, from
+	/// the app specific config directory, where it is kept in
+	/// `config_dir/app-name/{profile}.toml` next to the base
+	/// config.toml
+	///
+	/// # Notes
+	/// The base config.toml is loaded first, being created from the
+	/// template like [`load_in_dir`][Self::load_in_dir] does if it is
+	/// missing, and the profile file is merged over it. So the
+	/// profile only needs the keys it overrides, with tables merged
+	/// key by key and every other value in the profile replacing the
+	/// base one.
+	///
+	/// If the profile file doesn't exist the base config is used as
+	/// is, no profile file is created.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], for either file, and fails
+	/// with [`ConfigError::InvalidProfile`] if `profile` is empty or
+	/// contains a path separator or `..`
+	fn load_profile_in_dir(
+		app_name: &str,
+		profile: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		if profile.is_empty() || profile.contains('/') || profile.contains('\\') || profile.contains("..") {
+			return InvalidProfile { profile }.fail();
+		}
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+		let mut value = parse_value::<C>(C::FORMAT, &content, &config_location)?;
+
+		let profile_location =
+			config_location.with_file_name(format!("{}.{}", profile, C::FORMAT.extension()));
+		if profile_location.exists() {
+			let profile_content = read_file(&profile_location)?;
+			merge_values(
+				&mut value,
+				parse_value::<C>(C::FORMAT, &profile_content, &profile_location)?,
+			);
+		}
+
+		from_value(
+			with_defaults::<C>(migrated::<C>(value, &config_location)?),
+			&config_location,
+			&content,
+		)
+	}
+
+	/// Load the config from the OS specific config directory, merging
+	/// the local override file next to it over it\
+	/// This is a wrapper around
+	/// [`load_with_local_override_in_dir`][Self::
+	/// load_with_local_override_in_dir], which resolves the directory
+	/// the same way [`load`][Self::load] does.
+	fn load_with_local_override(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_local_override_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the app specific config directory,
+	/// merging `config_dir/app-name/config.local.toml` over the base
+	/// config.toml if it exists
+	///
+	/// # Notes
+	/// This suits a shared config.toml kept in version control along
+	/// with a gitignored config.local.toml for secrets and personal
+	/// tweaks, which only needs the keys it overrides. It is merged
+	/// the same way as a profile in
+	/// [`load_profile_in_dir`][Self::load_profile_in_dir], and the
+	/// extension follows the config's [`FORMAT`][Self::FORMAT].
+	/// The local file is named after [`file_name`][Self::file_name],
+	/// so a config stored in settings.toml is overridden by
+	/// settings.local.toml.
+	///
+	/// A missing local file isn't an error, the base config is used
+	/// as is.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], for either file
+	fn load_with_local_override_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let file_name = C::file_name();
+		let stem = Path::new(&file_name)
+			.file_stem()
+			.and_then(|s| s.to_str())
+			.unwrap_or("config");
+
+		Self::load_profile_in_dir(app_name, &format!("{}.local", stem), config_dir)
+	}
+
+	/// Load the config from the OS specific config directory, merging
+	/// the drop-in files of its `config.d` directory over it\
+	/// This is a wrapper around
+	/// [`load_with_drop_ins_in_dir`][Self::
+	/// load_with_drop_ins_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_drop_ins(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_drop_ins_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the app specific config directory,
+	/// merging every drop-in file in `config_dir/app-name/config.d/`
+	/// over the base config.toml, like the drop-in directories of
+	/// nginx or systemd
+	///
+	/// # Notes
+	/// The base config.toml is loaded first, being created from the
+	/// template like [`load_in_dir`][Self::load_in_dir] does if it is
+	/// missing. Every file in `config.d` with the extension of the
+	/// config's [`FORMAT`][Self::FORMAT] is then merged over it in
+	/// lexical order of their file names, so `20-local.toml` wins
+	/// over `10-package.toml`, which wins over the base file.
+	///
+	/// Tables are merged key by key, while any other value, including
+	/// arrays, replaces the one from the earlier file. A missing
+	/// `config.d` directory is the same as an empty one, and other
+	/// files in it are ignored.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], for the base file and
+	/// every drop-in, in addition to failing if `config.d` exists but
+	/// can't be read
+	fn load_with_drop_ins_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+		let mut value = parse_value::<C>(C::FORMAT, &content, &config_location)?;
+
+		for drop_in in drop_in_files(&config_location.with_file_name("config.d"), C::FORMAT)? {
+			let drop_in_content = read_file(&drop_in)?;
+			merge_values(
+				&mut value,
+				parse_value::<C>(C::FORMAT, &drop_in_content, &drop_in)?,
+			);
+		}
+
+		from_value(
+			with_defaults::<C>(migrated::<C>(value, &config_location)?),
+			&config_location,
+			&content,
+		)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, failing if the file contains keys
+	/// the config doesn't have\
+	/// This is a wrapper around
+	/// [`load_strict_in_dir`][Self::load_strict_in_dir], which
+	/// resolves the directory the same way [`load`][Self::load] does.
+	fn load_strict(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_strict_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, failing if the file contains keys
+	/// the config doesn't have, such as a typo'd `cliient_id`
+	///
+	/// # Notes
+	/// See [`load_with_unknown_keys_in_dir`][Self::
+	/// load_with_unknown_keys_in_dir] for which keys are known.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], in addition to failing
+	/// with [`ConfigError::UnknownKeys`] listing every unknown key
+	fn load_strict_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let (config, keys) = Self::load_with_unknown_keys_in_dir(app_name, config_dir)?;
+		if !keys.is_empty() {
+			let path = config_location(config_dir, app_name, &C::file_name())?;
+			return UnknownKeys { keys, path }.fail();
+		}
+
+		Ok(config)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, along with the keys in the file the
+	/// config doesn't have\
+	/// This is a wrapper around
+	/// [`load_with_unknown_keys_in_dir`][Self::
+	/// load_with_unknown_keys_in_dir], which resolves the directory
+	/// the same way [`load`][Self::load] does.
+	fn load_with_unknown_keys(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(C, Vec<String>)> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_unknown_keys_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, along with the keys in the file the
+	/// config doesn't have, so they can be reported as warnings
+	///
+	/// # Notes
+	/// The known keys are the ones in
+	/// [`template_fields`][Self::template_fields], including skipped
+	/// ones, with keys in tables named by their dotted path like
+	/// `server.port`. Configs from neither derive macro have no
+	/// template fields, so no unknown keys are reported for them.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_unknown_keys_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(C, Vec<String>)> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+		let value = migrated::<C>(
+			parse_value::<C>(C::FORMAT, &content, &config_location)?,
+			&config_location,
+		)?;
+
+		let fields = C::template_fields();
+		let mut keys = if fields.is_empty() {
+			Vec::new()
+		} else {
+			template::unknown_keys(&value, &fields)
+		};
+		// The version is added by migrations whether or not the config
+		// has a field for it
+		if C::VERSION > 0 {
+			keys.retain(|k| k != "version");
+		}
+		let config = from_value(with_defaults::<C>(value), &config_location, &content)?;
+
+		Ok((config, keys))
+	}
+
+	/// Load the config from whichever supported config file exists in
+	/// the OS specific config directory\
+	/// This is a wrapper around
+	/// [`load_auto_in_dir`][Self::load_auto_in_dir], which resolves
+	/// the directory the same way [`load`][Self::load] does.
+	fn load_auto(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_auto_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from whichever supported config file exists in
+	/// `config_dir/app-name/`, picking the parser from the file's
+	/// extension
+	///
+	/// # Notes
+	/// The extensions are tried in the order `toml`, `json`, `yaml`,
+	/// `yml`, skipping those whose format feature isn't enabled, and
+	/// the first existing file is loaded. Each one replaces the
+	/// extension of [`file_name`][Self::file_name], so a config
+	/// stored in settings.toml is also found as settings.json.
+	///
+	/// If none of them exist, this falls back to
+	/// [`load_in_dir`][Self::load_in_dir], creating a template in the
+	/// config's [`FORMAT`][Self::FORMAT].
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_auto_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let app_dir = config_dir.join(app_dir_name(app_name)?);
+		for &(extension, format) in format::EXTENSIONS {
+			let config_location = app_dir.join(C::file_name()).with_extension(extension);
+			if config_location.exists() {
+				return read_config(format, &config_location);
+			}
+		}
+
+		Self::load_in_dir(app_name, config_dir)
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory which is
+	/// `config_dir/app-name/config.toml`
+	///
+	/// # Notes
+	/// Unlike [`load_in_dir`][Self::load_in_dir] this pushes the app
+	/// name and config.toml onto `config_dir`, leaving it pointing at
+	/// the config file once the call returns.
+	#[deprecated(
+		since = "0.8.0",
+		note = "use `load_in_dir`, which doesn't modify the passed path"
+	)]
+	fn load_with_dir(
+		app_name: &str,
+		config_dir: &mut PathBuf,
+	) -> Result<C> {
+		let config = Self::load_in_dir(app_name, config_dir);
+		config_dir.push(app_dir_name(app_name)?);
+		config_dir.push(C::file_name());
+
+		config
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, along with the path of the file it
+	/// was loaded from\
+	/// This is a wrapper around
+	/// [`load_with_path_in_dir`][Self::load_with_path_in_dir], which
+	/// resolves the directory the same way [`load`][Self::load] does.
+	fn load_with_path(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(C, PathBuf)> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_path_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, along with the path of the file it
+	/// was loaded from
+	///
+	/// # Notes
+	/// The returned path is canonicalized if possible, otherwise it
+	/// is `config_dir/app-name/config.toml` as is.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_path_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(C, PathBuf)> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let config = read_config(C::FORMAT, &config_location)?;
+		let config_location = config_location.canonicalize().unwrap_or(config_location);
+
+		Ok((config, config_location))
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, along with the time the file was
+	/// last modified, to check it against with
+	/// [`is_stale`][Self::is_stale] later\
+	/// This is a wrapper around
+	/// [`load_with_modified_in_dir`][Self::
+	/// load_with_modified_in_dir], which resolves the directory the
+	/// same way [`load`][Self::load] does.
+	fn load_with_modified(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(C, Option<SystemTime>)> {
+		in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_with_modified_in_dir(app_name, dir)
+		})
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, along with the time the file was
+	/// last modified
+	///
+	/// # Notes
+	/// The time is `None` on filesystems which don't record it. It is
+	/// taken before the file is read, so a change made while reading
+	/// still makes the config stale.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir]
+	fn load_with_modified_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(C, Option<SystemTime>)> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let modified = modified_time(&config_location);
+		let config = read_config(C::FORMAT, &config_location)?;
+
+		Ok((config, modified))
+	}
+
+	/// Check whether the config file [`load`][Self::load] would read
+	/// was modified after `since`, without reading it, e.g. to poll
+	/// for changes instead of [watching][Self::watch] the file\
+	/// The file is located by [`config_path`][Self::config_path].
+	///
+	/// # Notes
+	/// A missing file, or one on a filesystem which doesn't record
+	/// modification times, is always stale, so polling falls back to
+	/// reloading. Modification times are only as precise as the
+	/// filesystem, which can be seconds.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`config_path`][Self::config_path]
+	fn is_stale(
+		app_name: &str,
+		force_user_dir: bool,
+		since: SystemTime,
+	) -> Result<bool> {
+		Ok(modified_since(
+			&Self::config_path(app_name, force_user_dir)?,
+			since,
+		))
+	}
+
+	/// Check whether the config file in the app specific config
+	/// directory was modified after `since`, like
+	/// [`is_stale`][Self::is_stale]
+	///
+	/// # Failures
+	/// This only fails if the app name is invalid
+	fn is_stale_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+		since: SystemTime,
+	) -> Result<bool> {
+		Ok(modified_since(
+			&config_location(config_dir, app_name, &C::file_name())?,
+			since,
+		))
+	}
+
+	/// Get the path of the config file [`load`][Self::load] would
+	/// read, without reading or creating anything
+	///
+	/// # Notes
+	/// Unless `force_user_dir` is set, this is the first existing
+	/// file in the system configuration directories listed by
+	/// [`search_paths`][Self::search_paths], otherwise it is the file
+	/// in the user configuration directory, whether or not that
+	/// exists.
+	///
+	/// # Failures
+	/// This can only fail due to the user configuration path not
+	/// being found
+	fn config_path(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<PathBuf> {
+		let file_name = C::file_name();
+		let dir = existing_config_dir(
+			app_name,
+			&file_name,
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		config_location(&dir, app_name, &file_name)
+	}
+
+	/// Check whether the config file [`load`][Self::load] would read
+	/// exists, without reading or creating anything, such as to
+	/// decide whether to run an init flow\
+	/// This checks the path returned by
+	/// [`config_path`][Self::config_path].
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`config_path`][Self::config_path]
+	fn exists(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<bool> {
+		Ok(Self::config_path(app_name, force_user_dir)?.is_file())
+	}
+
+	/// Check whether the config file located in the app specific
+	/// config directory exists, without reading or creating anything
+	///
+	/// # Failures
+	/// This only fails if the app name is invalid
+	fn exists_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<bool> {
+		Ok(config_location(config_dir, app_name, &C::file_name())?.is_file())
+	}
+
+	/// A [`figment`] provider of the config file [`load`][Self::load]
+	/// would read, for combining it with other sources such as
+	/// environment variables or command line arguments\
+	/// The file is located by [`config_path`][Self::config_path] and
+	/// isn't created.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`config_path`][Self::config_path], failures reading the file
+	/// are reported by figment when extracting
+	#[cfg(feature = "figment")]
+	fn provider(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<ConfigProvider<C>> {
+		Ok(ConfigProvider::new(Self::config_path(app_name, force_user_dir)?))
+	}
+
+	/// A [`figment`] provider of the config file in the app specific
+	/// config directory, like [`provider`][Self::provider]
+	///
+	/// # Failures
+	/// This only fails if the app name is invalid
+	#[cfg(feature = "figment")]
+	fn provider_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<ConfigProvider<C>> {
+		Ok(ConfigProvider::new(config_location(
+			config_dir,
+			app_name,
+			&C::file_name(),
+		)?))
+	}
+
+	/// Load the config file located in the OS specific config
+	/// directory as a [`toml::Value`] instead of deserializing it
+	/// into `C`, for keys only known at runtime\
+	/// This is a wrapper around
+	/// [`load_raw_in_dir`][Self::load_raw_in_dir], which resolves the
+	/// directory the same way [`load`][Self::load] does.
+	fn load_raw(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<toml::Value> {
+		let value = in_config_dirs(force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+			Self::load_raw_in_dir(app_name, dir)
+		});
+
+		with_search_paths::<C, _>(value, app_name, force_user_dir)
+	}
+
+	/// Load the config file located in the app specific config
+	/// directory as a [`toml::Value`], creating it from the template
+	/// if it doesn't exist the same way
+	/// [`load_in_dir`][Self::load_in_dir] does
+	///
+	/// # Notes
+	/// The value is what the file contains in any format, without
+	/// migrations, [`defaults`][Self::defaults] or validation
+	/// applied, and it may hold keys `C` doesn't know.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_in_dir`][Self::load_in_dir], except that the file only
+	/// has to be valid data in the config's [`FORMAT`][Self::FORMAT]
+	fn load_raw_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<toml::Value> {
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+		let content = read_file(&config_location)?;
+
+		parse_value::<C>(C::FORMAT, &content, &config_location)
+	}
+
+	/// Load the config from a single top-level table of the config
+	/// file located in the OS specific config directory, such as when
+	/// several subsystems share one file\
+	/// This is a wrapper around
+	/// [`load_section_in_dir`][Self::load_section_in_dir], which
+	/// reads the file [`config_path`][Self::config_path] returns.
+	fn load_section(
+		app_name: &str,
+		section: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::load_section_in_dir(app_name, section, &dir)
+	}
+
+	/// Load the config from the top-level table named `section` of
+	/// the config file located in the app specific config directory,
+	/// so `[network]` is read into `C` while the rest of the file is
+	/// ignored
+	///
+	/// # Notes
+	/// The file is shared with other configs, so it isn't created
+	/// from the template if it is missing. The config's
+	/// [`defaults`][Self::defaults] fill in keys missing from the
+	/// section.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config file doesn't exist or could not be read\
+	/// * If the file is not valid data in the config's
+	///   [`FORMAT`][Self::FORMAT]\
+	/// * With [`ConfigError::MissingSection`] if the file has no
+	///   table named `section`\
+	/// * If the section is not a valid config
+	fn load_section_in_dir(
+		app_name: &str,
+		section: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		let config_location = config_location(config_dir, app_name, &C::file_name())?;
+		let content = read_file(&config_location)?;
+		let value = match parse_value::<C>(C::FORMAT, &content, &config_location)?.get(section) {
+			Some(value @ toml::Value::Table(_)) => value.clone(),
+			_ =>
+				return MissingSection {
+					section,
+					path: config_location,
+				}
+				.fail(),
+		};
+
+		from_value(with_defaults::<C>(value), &config_location, &content)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, without creating anything if it
+	/// doesn't exist\
+	/// This is a wrapper around
+	/// [`load_no_create_in_dir`][Self::load_no_create_in_dir], which
+	/// reads the file [`config_path`][Self::config_path] resolves to.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`load_no_create_in_dir`][Self::load_no_create_in_dir], except
+	/// that a missing file fails with [`ConfigError::NoValidConfig`]
+	/// listing every path that was searched
+	fn load_no_create(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		with_search_paths::<C, _>(
+			Self::load_no_create_in_dir(app_name, &dir),
+			app_name,
+			force_user_dir,
+		)
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, without creating anything if it
+	/// doesn't exist
+	///
+	/// # Notes
+	/// Unlike [`load_in_dir`][Self::load_in_dir] this never writes to
+	/// the filesystem, so it can be used in sandboxed or read-only
+	/// environments.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config.toml doesn't exist, with
+	///   [`ConfigError::NotFound`]\
+	/// * If the config.toml could not be read properly\
+	/// * If the config.toml is not valid data in the config's
+	///   [`FORMAT`][Self::FORMAT]
+	fn load_no_create_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C> {
+		read_config(
+			C::FORMAT,
+			&config_location(config_dir, app_name, &C::file_name())?,
+		)
+	}
+
+	/// Load the config from the config file located in the OS
+	/// specific config directory, or its default value if the file
+	/// doesn't exist\
+	/// This is a wrapper around
+	/// [`load_or_default_in_dir`][Self::load_or_default_in_dir],
+	/// which resolves the directory the same way [`load`][Self::load]
+	/// does.
+	///
+	/// # Notes
+	/// Unless `force_user_dir` is set, the first file that exists in
+	/// the system configuration directories listed by
+	/// [`search_paths`][Self::search_paths] is read, otherwise the
+	/// user configuration directory is used.
+	fn load_or_default(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<C>
+	where
+		C: Default,
+	{
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::load_or_default_in_dir(app_name, &dir)
+	}
+
+	/// Load the config from the config file located in the app
+	/// specific config directory, or its default value if the file
+	/// doesn't exist
+	///
+	/// # Notes
+	/// Unlike [`load_in_dir`][Self::load_in_dir] this never touches
+	/// the filesystem when the file is missing, no directories or
+	/// template are created.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config.toml exists but could not be read properly\
+	/// * If the config.toml exists but is not valid data in the
+	///   config's [`FORMAT`][Self::FORMAT]
+	fn load_or_default_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<C>
+	where
+		C: Default,
+	{
+		let config_location = config_location(config_dir, app_name, &C::file_name())?;
+		if !config_location.exists() {
+			return Ok(C::default());
+		}
+
+		read_config(C::FORMAT, &config_location)
+	}
+
+	/// Save the config to the config file located in the OS specific
+	/// config directory\
+	/// This is a wrapper around
+	/// [`save_in_dir`][Self::save_in_dir], which resolves the
+	/// directory the same way [`load`][Self::load] does.
+	///
+	/// # Notes
+	/// Unless `force_user_dir` is set, the config is written to the
+	/// first system configuration directory listed by
+	/// [`search_paths`][Self::search_paths] a config.toml already
+	/// exists in, as that is the file `load` would have read.
+	/// Otherwise the user configuration directory is used.
+	///
+	/// # Failures
+	/// this will contain the same failure possibilities as
+	/// [`save_in_dir`][Self::save_in_dir] in addition to failing
+	/// if the user configuration path could not be found
+	fn save(
+		&self,
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<()>
+	where
+		Self: serde::Serialize,
+	{
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		self.save_in_dir(app_name, &dir)
+	}
+
+	/// Save the config to the config file located in the app specific
+	/// config directory which is `config_dir/app-name/config.toml`,
+	/// overwriting the file if it already exists
+	///
+	/// An existing TOML file keeps its comments, whitespace and key
+	/// order, only the values that changed are updated in place.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the app-name directory could not be created\
+	/// * If the config could not be serialized to the config's
+	///   [`FORMAT`][Self::FORMAT]\
+	/// * If the config.toml could not be written
+	fn save_in_dir(
+		&self,
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<()>
+	where
+		Self: serde::Serialize,
+	{
+		save_value::<C, _>(app_name, config_dir, self)
+	}
+
+	/// Save only the values of the config that differ from the
+	/// [`Default`] config to the config file located in the OS
+	/// specific config directory\
+	/// This is a wrapper around
+	/// [`save_minimal_in_dir`][Self::save_minimal_in_dir], which
+	/// resolves the directory the same way [`save`][Self::save] does.
+	fn save_minimal(
+		&self,
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<()>
+	where
+		Self: serde::Serialize + Default,
+	{
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		self.save_minimal_in_dir(app_name, &dir)
+	}
+
+	/// Save only the keys whose values differ from the [`Default`]
+	/// config, as listed by
+	/// [`diff_from_default`][Self::diff_from_default], to the config
+	/// file located in the app specific config directory
+	///
+	/// # Notes
+	/// This keeps config files small, and lets later changes to the
+	/// defaults apply to the keys users never set, as long as the
+	/// config fills in missing keys from its
+	/// [`defaults`][Self::defaults] like `#[configr(default)]` does.
+	/// Keys with default values are removed from an existing file.
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`save_in_dir`][Self::save_in_dir], in addition to
+	/// [`ConfigError::Diff`] if the default config can't be
+	/// represented as a [`toml::Value`]
+	fn save_minimal_in_dir(
+		&self,
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<()>
+	where
+		Self: serde::Serialize + Default,
+	{
+		let serialize_error = |source| ConfigError::Diff {
+			source: FormatError::TomlSer { source },
+		};
+		let default = toml::Value::try_from(Self::default()).map_err(serialize_error)?;
+		let value = toml::Value::try_from(self).map_err(serialize_error)?;
+		let minimal = without_defaults(value, Some(&default))
+			.unwrap_or_else(|| toml::Value::Table(toml::value::Table::new()));
+
+		save_value::<C, _>(app_name, config_dir, &minimal)
+	}
+
+	/// Create the config file in the OS specific config directory
+	/// from the template, such as for an "init" subcommand, returning
+	/// its path\
+	/// This is a wrapper around [`init_in_dir`][Self::init_in_dir],
+	/// which resolves the file the same way [`save`][Self::save]
+	/// does, so an existing system config file is never overwritten.
+	fn init(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<PathBuf> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::init_in_dir(app_name, &dir)
+	}
+
+	/// Create the config file located in the app specific config
+	/// directory from the template, creating the app-name directory
+	/// if needed, and return its path without reading it
+	///
+	/// # Notes
+	/// This writes the same template
+	/// [`load_in_dir`][Self::load_in_dir] creates a missing file
+	/// with, but as an explicit step instead.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config file already exists, which is left untouched\
+	/// * If the app-name directory or the file could not be created\
+	/// * If the template could not be written
+	fn init_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<PathBuf> {
+		let mut config_location = config_dir.join(app_dir_name(app_name)?);
+		create_app_dir(&config_location)?;
+		config_location.push(C::file_name());
+
+		// Opening with create_new instead of checking whether the file
+		// exists first avoids racing with other processes creating it.
+		let fd = match new_file_options(C::FILE_MODE)
+			.create_new(true)
+			.open(&config_location)
+		{
+			Ok(fd) => fd,
+			Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists =>
+				return AlreadyExists {
+					path: config_location,
+				}
+				.fail(),
+			Err(source) =>
+				return Err(source).context(CreateFs {
+					path: &config_location,
+				}),
+		};
+		lock::exclusive(&fd).context(CreateFs {
+			path: &config_location,
+		})?;
+		C::populate_template(fd).map_err(|e| template_error(e, &config_location))?;
+		event!(
+			info,
+			"Created configuration file {} from the template",
+			config_location.display()
+		);
+
+		Ok(config_location)
+	}
+
+	/// Delete the config file located in the OS specific config
+	/// directory, such as for a "forget my settings" action\
+	/// This is a wrapper around
+	/// [`delete_in_dir`][Self::delete_in_dir], which resolves the
+	/// file the same way [`save`][Self::save] does.
+	fn delete(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<()> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::delete_in_dir(app_name, &dir)
+	}
+
+	/// Delete the config file located in the app specific config
+	/// directory, along with the app-name directory if that is empty
+	/// afterwards
+	///
+	/// # Notes
+	/// A missing file isn't an error, so deleting twice is fine. An
+	/// app-name directory still containing other files, such as
+	/// profiles or drop-ins, is left alone.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::DeleteConfig`] if the file
+	/// exists but could not be removed
+	fn delete_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<()> {
+		let config_location = config_location(config_dir, app_name, &C::file_name())?;
+		match std::fs::remove_file(&config_location) {
+			Ok(()) => {},
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+			Err(source) =>
+				return Err(source).context(DeleteConfig {
+					path: &config_location,
+				}),
+		}
+		if let Some(app_dir) = config_location.parent() {
+			// Fails if the directory isn't empty, which is fine
+			let _ = std::fs::remove_dir(app_dir);
+		}
+
+		Ok(())
+	}
+
+	/// Overwrite the config file located in the OS specific config
+	/// directory with the default template, such as for a "restore
+	/// defaults" action\
+	/// This is a wrapper around
+	/// [`reset_to_default_in_dir`][Self::reset_to_default_in_dir],
+	/// which resolves the file the same way [`save`][Self::save]
+	/// does.
+	fn reset_to_default(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<()> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::reset_to_default_in_dir(app_name, &dir)
+	}
+
+	/// Overwrite the config file located in the app specific config
+	/// directory with the template a new config file is created with,
+	/// creating the app-name directory if needed
+	///
+	/// # Notes
+	/// Unlike deleting the file this leaves the template behind, so
+	/// the file only loads as is for a config with
+	/// `#[configr(default)]`, whose template is filled in from its
+	/// [`Default`]. Otherwise the required keys are left empty for
+	/// the user to fill in, as in a newly created file. The file is
+	/// replaced atomically the same way as by
+	/// [`save_in_dir`][Self::save_in_dir], keeping its permissions.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the app-name directory could not be created\
+	/// * If the template could not be written
+	fn reset_to_default_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<()> {
+		let mut config_location = config_dir.join(app_dir_name(app_name)?);
+		create_app_dir(&config_location)?;
+		config_location.push(C::file_name());
+
+		let template = C::template_string().map_err(|e| template_error(e, &config_location))?;
+		write_atomic(&config_location, template.as_bytes(), C::FILE_MODE).context(WriteTemplate {
+			path: &config_location,
+		})
+	}
+
+	/// Add the keys a newer version of the config introduced to the
+	/// config file [`load`][Self::load] would read, returning their
+	/// dotted paths\
+	/// This is a wrapper around
+	/// [`sync_template_in_dir`][Self::sync_template_in_dir], which
+	/// resolves the file the same way [`save`][Self::save] does.
+	fn sync_template(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<Vec<String>> {
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::sync_template_in_dir(app_name, &dir)
+	}
+
+	/// Add the keys of the config missing from the config file
+	/// located in the app specific config directory, the way the
+	/// template writes them, returning their dotted paths
+	///
+	/// # Notes
+	/// The values, comments and layout already in the file are left
+	/// as they are. Missing keys are written with their default value
+	/// and doc comments, or commented out if they have no default, so
+	/// the file keeps parsing until they are filled in.
+	///
+	/// A missing file is created from the template instead, and only
+	/// TOML files are synced, files in other formats are left as is.
+	/// In both cases no keys are returned.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config file could not be created or read\
+	/// * If the config file is not valid TOML\
+	/// * If the updated file could not be written
+	fn sync_template_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<Vec<String>> {
+		let (config_location, created) =
+			create_config_file_reported::<C>(config_dir.join(app_dir_name(app_name)?), &C::file_name())?;
+		if created || C::FORMAT != Format::Toml {
+			return Ok(Vec::new());
+		}
+		let content = read_file(&config_location)?;
+		let value = parse_value::<C>(C::FORMAT, &content, &config_location)?;
+
+		let (content, added) =
+			template::sync_toml(&content, &value, &C::template_fields(), C::defaults().as_ref());
+		if !added.is_empty() {
+			write_atomic(&config_location, content.as_bytes(), C::FILE_MODE).context(WriteConfig {
+				path: &config_location,
+			})?;
+		}
+
+		Ok(added)
+	}
+
+	/// Watch the config file located in the OS specific config
+	/// directory, calling `callback` with the reloaded config
+	/// whenever it changes\
+	/// This is a wrapper around [`watch_in_dir`][Self::watch_in_dir],
+	/// which watches the file [`load`][Self::load] would read.
+	#[cfg(feature = "watch")]
+	fn watch<F>(
+		app_name: &str,
+		force_user_dir: bool,
+		callback: F,
+	) -> Result<ConfigWatcher>
+	where
+		C: 'static,
+		F: FnMut(Result<C>) + Send + 'static,
+	{
+		let dir = existing_config_dir(
+			app_name,
+			&C::file_name(),
+			force_user_dir,
+			C::LOCAL_APP_DATA,
+			C::CURRENT_DIR,
+		)?;
+
+		Self::watch_in_dir(app_name, &dir, callback)
+	}
+
+	/// Watch the config file located in the app specific config
+	/// directory, calling `callback` with the reloaded config
+	/// whenever it changes
+	///
+	/// # Notes
+	/// The file is created from the template if it doesn't exist yet,
+	/// but isn't loaded, so the callback only runs for changes made
+	/// after this returns. It is called from a background thread,
+	/// with rapid successive writes debounced into a single reload.
+	///
+	/// Errors from reading or parsing the changed file are passed to
+	/// the callback, and watching continues. Watching stops once the
+	/// returned [`ConfigWatcher`] is dropped.
+	///
+	/// # Failures
+	/// This function will Error under the following circumstances\
+	/// * If the config.toml or the app-name directory could not be
+	///   created\
+	/// * If the template could not be written to the newly created
+	///   config.toml\
+	/// * If the filesystem watcher could not be set up
+	#[cfg(feature = "watch")]
+	fn watch_in_dir<F>(
+		app_name: &str,
+		config_dir: &Path,
+		callback: F,
+	) -> Result<ConfigWatcher>
+	where
+		C: 'static,
+		F: FnMut(Result<C>) + Send + 'static,
+	{
+		let config_location = create_config_file::<C>(app_name, &C::file_name(), config_dir)?;
+
+		watch::watch(C::FORMAT, config_location, callback)
+	}
+
+	/// Watch the config file like [`watch`][Self::watch], sending the
+	/// reloaded config to the returned receiver instead of calling a
+	/// callback\
+	/// Parse errors are sent as well, leaving it to the receiver
+	/// whether to keep using the last config that loaded. The channel
+	/// is closed once the [`ConfigWatcher`] is dropped.
+	#[cfg(feature = "watch")]
+	fn watch_channel(
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<(Receiver<Result<C>>, ConfigWatcher)>
+	where
+		C: Send + 'static,
+	{
+		let (tx, rx) = channel();
+		let watcher = Self::watch(app_name, force_user_dir, move |config| {
+			let _ = tx.send(config);
+		})?;
+
+		Ok((rx, watcher))
+	}
+
+	/// Watch the config file in the app specific config directory
+	/// like [`watch_in_dir`][Self::watch_in_dir], sending the
+	/// reloaded config to the returned receiver, see
+	/// [`watch_channel`][Self::watch_channel]
+	#[cfg(feature = "watch")]
+	fn watch_channel_in_dir(
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<(Receiver<Result<C>>, ConfigWatcher)>
+	where
+		C: Send + 'static,
+	{
+		let (tx, rx) = channel();
+		let watcher = Self::watch_in_dir(app_name, config_dir, move |config| {
+			let _ = tx.send(config);
+		})?;
+
+		Ok((rx, watcher))
+	}
+
+	/// Upgrade the raw contents of a config file written for
+	/// `from_version` of the schema to `from_version + 1`\
+	/// When the file's `version` is older than
+	/// [`VERSION`][Self::VERSION] this runs once for every version in
+	/// between, oldest first, after which `version` is set to the
+	/// current one and the result is deserialized. By default this
+	/// runs the step of [`migrations`][Self::migrations] for
+	/// `from_version`, the derive macros can set it with
+	/// `#[configr(migrate = "...")]`, naming a function with the same
+	/// signature
+	fn migrate(
+		raw: toml::Value,
+		from_version: u32,
+	) -> toml::Value {
+		match Self::migrations().get(from_version as usize) {
+			Some(step) => step(raw),
+			None => raw,
+		}
+	}
+
+	/// The steps upgrading the raw contents of a config file, where
+	/// the first one upgrades version 0 to version 1, the second one
+	/// version 1 to version 2 and so on up to
+	/// [`VERSION`][Self::VERSION]\
+	/// This is the declarative alternative to overriding
+	/// [`migrate`][Self::migrate], versions without a step are left
+	/// unchanged. Defaults to no steps, the derive macros can set it
+	/// with `#[configr(migrations = "...")]`, naming a function
+	/// returning the steps
+	fn migrations() -> Vec<fn(toml::Value) -> toml::Value> { Vec::new() }
+
+	/// The values used for keys missing from the config file\
+	/// By default every key has to be present in the file, while with
+	/// `#[configr(default)]` the derive uses the config's [`Default`]
+	/// value, so older config files keep loading when new fields are
+	/// added
+	fn defaults() -> Option<toml::Value> { None }
+
+	/// Merge `other` over this config the same way config files are
+	/// layered, see [`merge_values`]\
+	/// Nested tables are merged key by key, while scalars and arrays
+	/// in `other` replace the ones in `self`. `None` fields aren't
+	/// serialized, so they keep the value in `self`.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::Merge`] if either config can't
+	/// be represented as a [`toml::Value`], or the merged value isn't
+	/// a valid config
+	fn merge(
+		&mut self,
+		other: C,
+	) -> Result<()>
+	where
+		Self: Sized + serde::Serialize + serde::de::DeserializeOwned,
+		C: serde::Serialize,
+	{
+		let serialize_error = |source| ConfigError::Merge {
+			source: FormatError::TomlSer { source },
+		};
+		let mut base = toml::Value::try_from(&*self).map_err(serialize_error)?;
+		let overlay = toml::Value::try_from(other).map_err(serialize_error)?;
+		merge_values(&mut base, overlay);
+		*self = base.try_into().map_err(|source| ConfigError::Merge {
+			source: FormatError::TomlDe { source },
+		})?;
+
+		Ok(())
+	}
+
+	/// The keys whose values in this config differ from the
+	/// [`Default`] config, such as for a `myapp config diff`
+	/// subcommand, along with the default and current value\
+	/// Keys are dotted paths into nested tables, compared key by key
+	/// like [`merge`][Self::merge] layers them. The default value is
+	/// `None` for keys the default leaves unset, while keys unset in
+	/// this config, such as `None` fields, aren't reported.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::Diff`] if either config can't
+	/// be represented as a [`toml::Value`]
+	fn diff_from_default(&self) -> Result<Vec<(String, Option<toml::Value>, toml::Value)>>
+	where
+		Self: serde::Serialize + Default,
+	{
+		let serialize_error = |source| ConfigError::Diff {
+			source: FormatError::TomlSer { source },
+		};
+		let default = toml::Value::try_from(Self::default()).map_err(serialize_error)?;
+		let value = toml::Value::try_from(self).map_err(serialize_error)?;
+		let mut diff = Vec::new();
+		push_diff(&mut diff, "", Some(&default), &value);
+
+		Ok(diff)
+	}
+
+	/// Write the current values of the config as TOML to `writer`,
+	/// such as for a `myapp config show` subcommand\
+	/// With `redact` the values of fields marked with
+	/// `#[configr(secret)]` are written as `"***"`, including the
+	/// ones in nested tables.
+	///
+	/// # Failures
+	/// This fails with [`ConfigError::Dump`] if the config can't be
+	/// serialized as TOML, and [`ConfigError::WriteDump`] if writing
+	/// to `writer` fails
+	fn dump(
+		&self,
+		mut writer: impl std::io::Write,
+		redact: bool,
+	) -> Result<()>
+	where
+		Self: serde::Serialize,
+	{
+		let serialize_error = |source| ConfigError::Dump {
+			source: FormatError::TomlSer { source },
+		};
+		let mut value = toml::Value::try_from(self).map_err(serialize_error)?;
+		if redact {
+			template::redact(&mut value, &C::template_fields());
+		}
+		let content = toml::to_string(&value).map_err(serialize_error)?;
+
+		writer
+			.write_all(content.as_bytes())
+			.and_then(|()| writer.flush())
+			.context(WriteDump)
+	}
+
+	/// A [JSON Schema](https://json-schema.org) describing the config
+	/// file, generated by [`schemars`] from the config's
+	/// [`JsonSchema`][schemars::JsonSchema] implementation\
+	/// Editors can validate and autocomplete config files against it,
+	/// such as when written out by a `myapp config schema`
+	/// subcommand.
+	///
+	/// # Panics
+	/// If the generated schema can't be converted to JSON, which
+	/// schemars' schemas always can
+	#[cfg(feature = "schemars")]
+	fn schema() -> serde_json::Value
+	where
+		C: schemars::JsonSchema,
+	{
+		serde_json::to_value(schemars::schema_for!(C)).expect("JSON Schemas always serialize")
+	}
+
+	/// Check that a successfully parsed config is valid, which every
+	/// load calls before returning it\
+	/// Returning `Err` makes the load fail with
+	/// [`ConfigError::Validation`] holding the message, by default
+	/// every config is valid
+	///
+	/// # Notes
+	/// This only runs once the config file parsed, so it never sees a
+	/// partially read config, nor the default value
+	/// [`load_or_default`][Self::load_or_default] returns for a
+	/// missing file.
+	fn validate(&self) -> std::result::Result<(), String> { Ok(()) }
+
+	/// Describes the keys of the config, which the [`Configr`] derive
+	/// writes its template from, and configs containing this one use
+	/// to write it as a table\
+	/// Skipped fields are included but marked as such, so they are
+	/// still known keys for
+	/// [`load_strict`][Self::load_strict].\
+	/// Both derive macros fill this in, by default it is empty
+	fn template_fields() -> Vec<TemplateField> { Vec::new() }
+
+	/// Write the template new config files are created with to
+	/// `writer`, which is a newly created config file when loading
+	///
+	/// # Failures
+	/// Failing to write to `writer` should return
+	/// [`ConfigError::Template`], and failing to serialize the
+	/// template [`ConfigError::TemplateSerialize`], which loading
+	/// fills in the path of the config file for
+	fn populate_template(writer: impl std::io::Write) -> Result<()>;
+
+	/// The template new config files are created with, without
+	/// writing any file\
+	/// Useful for printing the template, e.g. from a
+	/// `--print-template` flag
+	///
+	/// # Failures
+	/// This fails if [`populate_template`][Self::populate_template]
+	/// does, or with [`ConfigError::Template`] if it writes invalid
+	/// UTF-8
+	fn template_string() -> Result<String> {
+		let mut template = Vec::new();
+		Self::populate_template(&mut template)?;
+
+		String::from_utf8(template)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+			.context(Template)
+	}
+}
+
+#[doc(hidden)]
+pub mod __private {
+	//! Support code for the derive macros, not part of the public API
+
+	use std::marker::PhantomData;
+
+	pub use serde::de::DeserializeOwned;
+	pub use serde::Serialize;
+
+	use crate::{Config, TemplateField};
+
+	/// Finds the template fields of `T` if it is a config itself, and
+	/// `None` for any other type\
+	/// This relies on method resolution trying [`SectionProbe`] on
+	/// `&Probe<T>` before autoref'ing to [`ValueProbe`] on
+	/// `&&Probe<T>`, so it only works for concrete types, like the
+	/// ones in the derive output.
+	pub struct Probe<T>(pub PhantomData<T>);
+
+	pub trait SectionProbe {
+		fn section_fields(&self) -> Option<Vec<TemplateField>>;
+	}
+
+	impl<T> SectionProbe for Probe<T>
+	where
+		T: serde::de::DeserializeOwned + Config<T>,
+	{
+		fn section_fields(&self) -> Option<Vec<TemplateField>> { Some(T::template_fields()) }
+	}
+
+	pub trait ValueProbe {
+		fn section_fields(&self) -> Option<Vec<TemplateField>>;
+	}
+
+	impl<T> ValueProbe for &Probe<T> {
+		fn section_fields(&self) -> Option<Vec<TemplateField>> { None }
+	}
+
+	/// Read the TOML value `raw` of `#[configr(default = "...")]` on
+	/// the field stored under `key` as a `T`, for the `Default` impl
+	/// of `#[configr(generate_default)]`
+	///
+	/// # Panics
+	/// If `raw` isn't a valid TOML value of the field's type
+	pub fn default_value<T>(
+		raw: &str,
+		key: &str,
+	) -> T
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		format!("value = {}", raw)
+			.parse::<toml::Value>()
+			.ok()
+			.and_then(|mut document| document.as_table_mut()?.remove("value"))
+			.and_then(|value| value.try_into().ok())
+			.unwrap_or_else(|| panic!("invalid #[configr(default = {:?})] on `{}`", raw, key))
+	}
+}
+
+/// Run `load` in the system configuration directory unless
+/// `force_user_dir` is set, falling back to the user configuration
+/// directory if that fails
+fn in_config_dirs<T>(
+	force_user_dir: bool,
+	local: bool,
+	current_dir: bool,
+	load: impl Fn(&Path) -> Result<T>,
+) -> Result<T> {
+	let system_dirs = if force_user_dir {
+		Vec::new()
+	} else {
+		system_config_dirs(current_dir)
+	};
+
+	in_dirs(&system_dirs, || user_config_dir(local), load)
+}
+
+/// Run `load` in each of `system_dirs` until it succeeds, falling
+/// back to the directory from `user_dir` if it fails in all of them
+fn in_dirs<T>(
+	system_dirs: &[PathBuf],
+	user_dir: impl FnOnce() -> Result<PathBuf>,
+	load: impl Fn(&Path) -> Result<T>,
+) -> Result<T> {
+	for dir in system_dirs {
+		match load(dir) {
+			Ok(t) => return Ok(t),
+			Err(error) => {
+				event!(
+					warn,
+					"Failed to load configuration from {}, trying the next directory: {}",
+					dir.display(),
+					error
+				);
+			},
+		}
+	}
+	let dir = user_dir()?;
+	event!(debug, "Loading configuration from {}", dir.display());
+
+	load(&dir)
+}
+
+/// The configuration directory containing the config file named
+/// `file_name`, which is the first system configuration directory
+/// the file exists in unless `force_user_dir` is set, and otherwise
+/// the user configuration directory
+fn existing_config_dir(
+	app_name: &str,
+	file_name: &str,
+	force_user_dir: bool,
+	local: bool,
+	current_dir: bool,
+) -> Result<PathBuf> {
+	existing_app_config_dir(
+		&app_dir_name(app_name)?,
+		file_name,
+		force_user_dir,
+		local,
+		current_dir,
+	)
+}
+
+/// [`existing_config_dir`] for an app directory named `app_dir`
+/// rather than an app name
+fn existing_app_config_dir(
+	app_dir: &str,
+	file_name: &str,
+	force_user_dir: bool,
+	local: bool,
+	current_dir: bool,
+) -> Result<PathBuf> {
+	if !force_user_dir {
+		for dir in system_config_dirs(current_dir) {
+			let path = dir.join(app_dir).join(file_name);
+			if path.exists() {
+				event!(debug, "Using the system configuration file {}", path.display());
+				return Ok(dir);
+			}
+			event!(debug, "No system configuration file at {}", path.display());
+		}
+	}
+
+	user_config_dir(local)
+}
+
+/// The user configuration directory, which is the local rather than
+/// the roaming app data directory on Windows if `local` is set, see
+/// [`Config::LOCAL_APP_DATA`]
+fn user_config_dir(local: bool) -> Result<PathBuf> {
+	if local && cfg!(windows) {
+		dirs::data_local_dir().context(ConfigDir)
+	} else {
+		dirs::config_dir().context(ConfigDir)
+	}
+}
+
+/// The system wide configuration directories in the order they are
+/// searched, which on UNIX systems are the absolute paths in
+/// `$XDG_CONFIG_DIRS`, or /etc/xdg if there are none, followed by
+/// [`SYSTEM_CONFIG_DIR`], and on other systems the current directory
+/// if `current_dir` is set, see [`Config::CURRENT_DIR`]
+fn system_config_dirs(current_dir: bool) -> Vec<PathBuf> {
+	system_config_dirs_from(std::env::var_os("XDG_CONFIG_DIRS"), current_dir)
+}
+
+/// [`system_config_dirs`] with `xdg_config_dirs` as the value of
+/// `$XDG_CONFIG_DIRS`
+fn system_config_dirs_from(
+	xdg_config_dirs: Option<std::ffi::OsString>,
+	current_dir: bool,
+) -> Vec<PathBuf> {
+	if !cfg!(target_family = "unix") {
+		return if current_dir {
+			vec![PathBuf::from("./")]
+		} else {
+			Vec::new()
+		};
+	}
+	let mut dirs: Vec<PathBuf> = xdg_config_dirs
+		.map(|dirs| {
+			std::env::split_paths(&dirs)
+				.filter(|dir| dir.is_absolute())
+				.collect()
+		})
+		.unwrap_or_default();
+	if dirs.is_empty() {
+		dirs.push(PathBuf::from("/etc/xdg"));
+	}
+	dirs.push(PathBuf::from(SYSTEM_CONFIG_DIR));
+
+	dirs
+}
+
+/// Get the location of the config file at
+/// `config_dir/app-name/file_name`, creating the directory and the
+/// file itself from the template if needed
+fn create_config_file<C>(
+	app_name: &str,
+	file_name: &str,
+	config_dir: &Path,
+) -> Result<PathBuf>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	create_config_file_in::<C>(config_dir.join(app_dir_name(app_name)?), file_name)
+}
+
+/// Get the location of the config file at `app_dir/file_name`,
+/// creating the directory and the file itself from the template if
+/// needed
+fn create_config_file_in<C>(
+	app_dir: PathBuf,
+	file_name: &str,
+) -> Result<PathBuf>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	create_config_file_reported::<C>(app_dir, file_name).map(|(path, _)| path)
+}
+
+/// Get the location of the config file at `app_dir/file_name` like
+/// [`create_config_file_in`], along with whether the file was created
+/// from the template by this call
+fn create_config_file_reported<C>(
+	app_dir: PathBuf,
+	file_name: &str,
+) -> Result<(PathBuf, bool)>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let mut config_location = app_dir;
+	create_app_dir(&config_location)?;
+	config_location.push(file_name);
+	// Checking whether the file exists before creating it would race
+	// with other processes creating it at the same time, so create it
+	// only if it's missing and leave an existing one alone.
+	let created = match new_file_options(C::FILE_MODE)
+		.create_new(true)
+		.open(&config_location)
+	{
+		Ok(fd) => {
+			lock::exclusive(&fd).context(CreateFs {
+				path: &config_location,
+			})?;
+			C::populate_template(fd).map_err(|e| template_error(e, &config_location))?;
+			event!(
+				info,
+				"Created configuration file {} from the template",
+				config_location.display()
+			);
+			true
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => false,
+		Err(source) =>
+			return Err(source).context(CreateFs {
+				path: &config_location,
+			}),
+	};
+
+	Ok((config_location, created))
+}
+
+/// Create the app-name directory `app_dir` if it doesn't exist yet,
+/// failing with [`ConfigError::NotADirectory`] if something else is
+/// in its place
+fn create_app_dir(app_dir: &Path) -> Result<()> {
+	if app_dir.is_dir() {
+		return Ok(());
+	}
+	if app_dir.exists() {
+		return NotADirectory { path: app_dir }.fail();
+	}
+
+	create_dir_all(app_dir).context(CreateFs { path: app_dir })
+}
+
+/// Attach the `path` of the config file a template is written to to
+/// the errors of [`populate_template`][Config::populate_template],
+/// which doesn't know it
+fn template_error(
+	error: ConfigError,
+	path: &Path,
+) -> ConfigError {
+	let path = path.to_path_buf();
+	match error {
+		ConfigError::Template { source } => ConfigError::WriteTemplate { source, path },
+		ConfigError::TemplateSerialize { source } => ConfigError::Serialize { source, path },
+		error => error,
+	}
+}
+
+/// Options for opening a config file for writing, which is created
+/// with the UNIX permissions `mode` if it doesn't exist
+fn new_file_options(mode: u32) -> OpenOptions {
+	let mut options = OpenOptions::new();
+	options.write(true).create(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+	#[cfg(not(unix))]
+	let _ = mode;
+
+	options
+}
+
+/// Distinguishes the temporary files of concurrent writes from the
+/// same process
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Create a new temporary file next to `path` with the UNIX
+/// permissions `mode`, returning its location
+///
+/// The file is always newly created, so an existing file or symlink
+/// left at a temporary location is skipped rather than written
+/// through.
+fn create_temp_file(
+	path: &Path,
+	mode: u32,
+) -> std::io::Result<(PathBuf, std::fs::File)> {
+	let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+	let mut attempts = 0;
+	loop {
+		let tmp_path = path.with_file_name(format!(
+			".{}.tmp-{}-{}",
+			file_name,
+			std::process::id(),
+			TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+		));
+		match new_file_options(mode).create_new(true).open(&tmp_path) {
+			Ok(fd) => return Ok((tmp_path, fd)),
+			Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 100 => attempts += 1,
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+/// Replace the file at `path` with `contents`, by writing them to a
+/// temporary file next to it which is then renamed over it, so
+/// readers never see a partially written file
+///
+/// An existing file keeps its permissions, a new one is created with
+/// the UNIX permissions `mode`. The temporary file is removed if
+/// anything fails.
+fn write_atomic(
+	path: &Path,
+	contents: &[u8],
+	mode: u32,
+) -> std::io::Result<()> {
+	let (tmp_path, mut fd) = create_temp_file(path, mode)?;
+	let written = fd
+		.write_all(contents)
+		.and_then(|()| fd.sync_all())
+		.and_then(|()| match std::fs::metadata(path) {
+			Ok(metadata) => fd.set_permissions(metadata.permissions()),
+			Err(_) => Ok(()),
+		});
+	drop(fd);
+	let written = written.and_then(|()| {
+		// Replacing the file waits for anyone still reading the old one
+		let old = std::fs::File::open(path).ok();
+		if let Some(old) = &old {
+			lock::exclusive(old)?;
+		}
+		std::fs::rename(&tmp_path, path)
+	});
+	if written.is_err() {
+		let _ = std::fs::remove_file(&tmp_path);
+	}
+
+	written
+}
+
+/// Read the config file at `path` and deserialize it from `format`,
+/// filling in any keys missing from the file with the config's
+/// [`defaults`][Config::defaults]
+fn read_config<C>(
+	format: Format,
+	path: &Path,
+) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("read_config", path = %path.display()).entered();
+	let content = read_file(path)?;
+
+	parse_config(format, &content, path)
+}
+
+/// Read the config file at `path` like [`read_config`], overriding
+/// its values with the environment variables named `{PREFIX}_{KEY}`
+/// if `prefix` is set, and then those named by the config's fields
+fn read_config_with_env<C>(
+	path: &Path,
+	prefix: Option<&str>,
+) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	read_config_with_vars(path, prefix, Vec::new())
+}
+
+/// Read the config file at `path` like [`read_config_with_env`],
+/// applying `vars` with the prefix before the environment so the
+/// environment takes precedence
+fn read_config_with_vars<C>(
+	path: &Path,
+	prefix: Option<&str>,
+	vars: Vec<(String, String)>,
+) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let content = read_file(path)?;
+	let mut value = with_defaults::<C>(migrated::<C>(parse_value::<C>(C::FORMAT, &content, path)?, path)?);
+	if let Some(prefix) = prefix {
+		env::apply_vars(&mut value, prefix, vars);
+		env::apply_env(&mut value, prefix);
+	}
+	env::apply_field_env(&mut value, &C::template_fields());
+
+	from_value(value, path, &content)
+}
+
+/// Deserialize the `content` of the config file at `path` from
+/// `format`, migrating it from older versions and filling in any keys
+/// missing from the file with the config's
+/// [`defaults`][Config::defaults]
+fn parse_config<C>(
+	format: Format,
+	content: &str,
+	path: &Path,
+) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	// Going through a `Value` loses the format specific error messages,
+	// so only do it when there's something to do with it.
+	if C::VERSION == 0 && C::defaults().is_none() {
+		let config = format
+			.deserialize::<C>(content)
+			.map_err(|source| deserialize_error::<C>(source, path, content))
+			.and_then(validated);
+		return logged(config, path);
+	}
+	let value = with_defaults::<C>(migrated::<C>(parse_value::<C>(format, content, path)?, path)?);
+
+	from_value(value, path, content)
+}
+
+/// The files in the drop-in directory `dir` with the extension of
+/// `format`, sorted by file name, or none if `dir` doesn't exist
+fn drop_in_files(
+	dir: &Path,
+	format: Format,
+) -> Result<Vec<PathBuf>> {
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(read_error(e, dir)),
+	};
+	let mut files = Vec::new();
+	for entry in entries {
+		let path = entry.map_err(|e| read_error(e, dir))?.path();
+		if path.is_file() && path.extension().map_or(false, |e| e == format.extension()) {
+			files.push(path);
+		}
+	}
+	files.sort();
+
+	Ok(files)
+}
+
+/// Read the contents of the config file at `path`, holding a shared
+/// lock on it with the `lock` feature so it isn't read while a
+/// template is still being written to it
+fn read_file(path: &Path) -> Result<String> {
+	event!(debug, "Reading configuration file {}", path.display());
+	let mut file = std::fs::File::open(path).map_err(|e| read_error(e, path))?;
+	// Directories can be opened on some systems, failing only when read
+	if file.metadata().map_or(false, |m| m.is_dir()) {
+		return NotAFile { path }.fail();
+	}
+	lock::shared(&file).map_err(|e| read_error(e, path))?;
+	let mut content = String::new();
+	std::io::Read::read_to_string(&mut file, &mut content).map_err(|e| read_error(e, path))?;
+
+	Ok(strip_bom(content))
+}
+
+/// The time the file at `path` was last modified, if the filesystem
+/// records it
+fn modified_time(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether the file at `path` was modified after `since`, which is
+/// assumed when the time isn't known
+fn modified_since(
+	path: &Path,
+	since: SystemTime,
+) -> bool {
+	modified_time(path).map_or(true, |modified| modified > since)
+}
+
+/// The byte order mark some Windows editors put at the start of UTF-8
+/// files
+const BOM: char = '\u{feff}';
+
+/// Remove a leading [`BOM`] from `content`, which parsers would
+/// otherwise reject as an invalid first character
+fn strip_bom(mut content: String) -> String {
+	if content.starts_with(BOM) {
+		content.drain(..BOM.len_utf8());
+	}
+	content
+}
+
+/// Turn an error from reading the config file at `path` into a
+/// [`ConfigError`], picking out the kinds callers may want to handle
+fn read_error(
+	source: std::io::Error,
+	path: &Path,
+) -> ConfigError {
+	let path = path.to_path_buf();
+	match source.kind() {
+		std::io::ErrorKind::NotFound => ConfigError::NotFound { path },
+		std::io::ErrorKind::PermissionDenied => ConfigError::PermissionDenied { path },
+		_ => ConfigError::ReadConfig { source, path },
+	}
+}
+
+/// Parse the `content` of the config file at `path` from `format`
+/// into a [`toml::Value`]
+fn parse_value<C>(
+	format: Format,
+	content: &str,
+	path: &Path,
+) -> Result<toml::Value>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	format
+		.deserialize_value(content)
+		.map_err(|source| deserialize_error::<C>(source, path, content))
+}
+
+/// The error for failing to parse the `content` of the config file at
+/// `path`, with the values of the config's secret fields redacted
+/// from both the content and the error message
+fn deserialize_error<C>(
+	source: FormatError,
+	path: &Path,
+	content: &str,
+) -> ConfigError
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let (toml, secrets) = template::redact_content(content, &C::template_fields());
+	let message = source.to_string();
+	let redacted = template::redact_message(&message, &secrets);
+	let source = if redacted == message {
+		source
+	} else {
+		source.with_message(&redacted)
+	};
+
+	Deserialize { path, toml }.into_error(source)
+}
+
+/// Run the config's [`migrate`][Config::migrate] steps from the
+/// `version` key of `value` read from `path` up to the current
+/// [`VERSION`][Config::VERSION], failing if the file's version is
+/// newer than that
+fn migrated<C>(
+	mut value: toml::Value,
+	path: &Path,
+) -> Result<toml::Value>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let version = value
+		.get("version")
+		.and_then(toml::Value::as_integer)
+		.map_or(0, |v| u32::try_from(v.max(0)).unwrap_or(u32::MAX));
+	// Without a version the key may well be something else entirely
+	if C::VERSION > 0 && version > C::VERSION {
+		return NewerVersion {
+			path,
+			version,
+			supported: C::VERSION,
+		}
+		.fail();
+	}
+	if version >= C::VERSION {
+		return Ok(value);
+	}
+	for from_version in version..C::VERSION {
+		value = C::migrate(value, from_version);
+	}
+	if let Some(table) = value.as_table_mut() {
+		table.insert("version".to_string(), toml::Value::Integer(C::VERSION.into()));
+	}
+
+	Ok(value)
+}
+
+/// Fill the keys missing from `value` with the config's
+/// [`defaults`][Config::defaults]
+fn with_defaults<C>(value: toml::Value) -> toml::Value
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	match C::defaults() {
+		Some(mut defaults) => {
+			merge_values(&mut defaults, value);
+			defaults
+		},
+		None => value,
+	}
+}
+
+/// Merge `overlay` into `base` the way configr layers config files
+///
+/// Tables are merged recursively, so keys only in `base` are kept and
+/// keys in both are merged again. Any other value in `overlay`,
+/// including arrays, replaces the one in `base` wholesale, even if
+/// the types differ.
+///
+/// ```
+/// use configr::toml;
+/// let mut base: toml::Value = toml::from_str("[db]\nhost = \"a\"\nport = 1").unwrap();
+/// let overlay: toml::Value = toml::from_str("[db]\nport = 2").unwrap();
+/// configr::merge_values(&mut base, overlay);
+/// assert_eq!(base, toml::from_str("[db]\nhost = \"a\"\nport = 2").unwrap());
+/// ```
+pub fn merge_values(
+	base: &mut toml::Value,
+	overlay: toml::Value,
+) {
+	match (base, overlay) {
+		(toml::Value::Table(base), toml::Value::Table(overlay)) =>
+			for (key, value) in overlay {
+				match base.get_mut(&key) {
+					Some(existing) => merge_values(existing, value),
+					None => {
+						base.insert(key, value);
+					},
+				}
+			},
+		(base, overlay) => *base = overlay,
+	}
+}
+
+/// Save `value` to the config file of `app_name` in `config_dir`, see
+/// [`save_in_dir`][Config::save_in_dir]
+fn save_value<C, T>(
+	app_name: &str,
+	config_dir: &Path,
+	value: &T,
+) -> Result<()>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+	T: serde::Serialize,
+{
+	let mut config_location = config_dir.join(app_dir_name(app_name)?);
+	create_app_dir(&config_location)?;
+	config_location.push(C::file_name());
+
+	// Update an existing TOML file in place so the comments and layout
+	// the user gave it survive the save
+	let existing = if C::FORMAT == Format::Toml {
+		std::fs::read_to_string(&config_location).ok().map(strip_bom)
+	} else {
+		None
+	};
+	let toml_content = match existing {
+		Some(content) => {
+			let value = toml::Value::try_from(value)
+				.map_err(|source| FormatError::TomlSer { source })
+				.context(Serialize {
+					path: &config_location,
+				})?;
+			edit::update_document(&content, &value)
+		},
+		_ => None,
+	};
+	let toml_content = match toml_content {
+		Some(content) => content,
+		None => C::FORMAT.serialize(value).context(Serialize {
+			path: &config_location,
+		})?,
+	};
+	write_atomic(&config_location, toml_content.as_bytes(), C::FILE_MODE).context(WriteConfig {
+		path: &config_location,
+	})
+}
+
+/// `value` without the keys whose values are the same as in `base`,
+/// looking into the tables of both, or `None` if nothing differs
+fn without_defaults(
+	value: toml::Value,
+	base: Option<&toml::Value>,
+) -> Option<toml::Value> {
+	match (value, base) {
+		(toml::Value::Table(table), Some(toml::Value::Table(base))) => {
+			let table: toml::value::Table = table
+				.into_iter()
+				.filter_map(|(key, value)| {
+					let value = without_defaults(value, base.get(&key))?;
+					Some((key, value))
+				})
+				.collect();
+			Some(toml::Value::Table(table)).filter(|table| table.as_table().map_or(false, |t| !t.is_empty()))
+		},
+		(value, base) => Some(value).filter(|value| base != Some(value)),
+	}
+}
+
+/// Add the dotted keys below `key` whose values in `value` differ
+/// from the ones in `base` to `diff`, looking into the tables of both
+fn push_diff(
+	diff: &mut Vec<(String, Option<toml::Value>, toml::Value)>,
+	key: &str,
+	base: Option<&toml::Value>,
+	value: &toml::Value,
+) {
+	let dotted = |nested: &str| match key {
+		"" => nested.to_string(),
+		key => format!("{}.{}", key, nested),
+	};
+	match (base, value) {
+		(Some(toml::Value::Table(base)), toml::Value::Table(table)) =>
+			for (nested, value) in table {
+				push_diff(diff, &dotted(nested), base.get(nested), value);
+			},
+		(None, toml::Value::Table(table)) =>
+			for (nested, value) in table {
+				push_diff(diff, &dotted(nested), None, value);
+			},
+		(base, value) if base != Some(value) => diff.push((key.to_string(), base.cloned(), value.clone())),
+		_ => {},
+	}
+}
+
+/// Deserialize a config from a [`toml::Value`] which was built from
+/// the `content` of the config file at `path`
+fn from_value<C>(
+	value: toml::Value,
+	path: &Path,
+	content: &str,
+) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	let config = value
+		.try_into()
+		.map_err(|source| deserialize_error::<C>(FormatError::TomlDe { source }, path, content))
+		.and_then(validated);
+
+	logged(config, path)
+}
+
+/// Log whether parsing the config file at `path` succeeded, returning
+/// the result as is
+fn logged<C>(
+	config: Result<C>,
+	path: &Path,
+) -> Result<C> {
+	match &config {
+		Ok(_) => {
+			event!(debug, "Parsed configuration file {}", path.display());
+		},
+		Err(error) => {
+			event!(
+				warn,
+				"Failed to parse configuration file {}: {}",
+				path.display(),
+				error
+			);
+		},
+	}
+
+	config
+}
+
+/// Run the config's [`validate`][Config::validate] hook, turning a
+/// rejection into [`ConfigError::Validation`]
+fn validated<C>(config: C) -> Result<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	config
+		.validate()
+		.map_err(|message| ConfigError::Validation { message })?;
+
+	Ok(config)
+}
+
+/// Turn a [`ConfigError::NotFound`] from loading the config into
+/// [`ConfigError::NoValidConfig`] listing the
+/// [`search_paths`][Config::search_paths], so the error shows
+/// everywhere the file was looked for
+fn with_search_paths<C, T>(
+	result: Result<T>,
+	app_name: &str,
+	force_user_dir: bool,
+) -> Result<T>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	match result {
+		Err(ConfigError::NotFound { path }) => NoValidConfig {
+			paths: C::search_paths(app_name, force_user_dir).unwrap_or_else(|_| vec![path]),
+		}
+		.fail(),
+		result => result,
+	}
+}
+
+/// The location of the config file at `config_dir/app-name/file_name`
+fn config_location(
+	config_dir: &Path,
+	app_name: &str,
+	file_name: &str,
+) -> Result<PathBuf> {
+	Ok(config_dir.join(app_dir_name(app_name)?).join(file_name))
+}
+
+/// The line of `content` that `error` points at with a caret under
+/// the column, along with `context` lines around it and every line
+/// cut to `width` characters, or nothing if the error has no position
+fn error_excerpt(
+	content: &str,
+	error: &FormatError,
+	context: usize,
+	width: usize,
+) -> String {
+	let (line, col) = match error.line_col() {
+		Some(line_col) => line_col,
+		None => return String::new(),
+	};
+	let lines: Vec<&str> = content.lines().collect();
+	if line >= lines.len() {
+		return String::new();
+	}
+	// Every line is cut at the same columns, so the caret stays under
+	// the error and the lines stay aligned.
+	let start = if lines[line].chars().count() <= width {
+		0
+	} else {
+		col.saturating_sub(width / 2)
+	};
+	let mut out = String::from("```\n");
+	for (i, text) in lines
+		.iter()
+		.enumerate()
+		.skip(line.saturating_sub(context))
+		.take(context.min(line) + 1 + context)
+	{
+		out.push_str(&elide(text, start, width));
+		out.push('\n');
+		if i == line {
+			let marker = if start > 0 { 3 } else { 0 };
+			out.push_str(&format!("{}^\n", " ".repeat(col - start + marker)));
+		}
+	}
+	out.push_str("```\n");
+
+	out
+}
+
+/// The `width` characters of `line` from `start` on, with `...` in
+/// place of the characters cut off at either end
+fn elide(
+	line: &str,
+	start: usize,
+	width: usize,
+) -> String {
+	let len = line.chars().count();
+	let mut out = String::new();
+	if start > 0 {
+		out.push_str("...");
+	}
+	out.extend(line.chars().skip(start).take(width));
+	if start + width < len {
+		out.push_str("...");
+	}
+	out
+}
+
+/// Join `paths` into a comma separated list for error messages
+fn display_paths(paths: &[PathBuf]) -> String {
+	paths
+		.iter()
+		.map(|p| p.display().to_string())
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Converts the app name into the lowercase-kebab-case directory
+/// name, rejecting names which would escape the config directory
+fn app_dir_name(app_name: &str) -> Result<String> {
+	checked_app_dir_name(app_name, app_name.replace(" ", "-").to_ascii_lowercase())
+}
+
+/// Use `name` as the directory name of the app called `app_name`,
+/// rejecting names which would escape the config directory
+fn checked_app_dir_name(
+	app_name: &str,
+	name: String,
+) -> Result<String> {
+	if name.is_empty() || name.contains('/') || name.contains('\\') || name.contains("..") {
+		return InvalidAppName { app_name }.fail();
+	}
+
+	Ok(name)
+}
+
+#[cfg(test)]
+mod configr_tests {
+	use configr::{Config, ConfigError, Configr};
+	use serde::{Deserialize, Serialize};
+
+	use crate as configr;
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	struct TestDefaultConfig {
+		a: String,
+		b: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestConfig {
+		a: String,
+		b: String,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	struct TestSkipDefaultConfig {
+		a: String,
+		#[configr(skip)]
+		computed: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestSkipConfig {
+		a: String,
+		#[configr(skip)]
+		#[serde(default)]
+		computed: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestRenameConfig {
+		#[serde(rename = "api-key")]
+		api_key: String,
+		#[configr(rename = "Secret")]
+		#[serde(rename(deserialize = "Secret"))]
+		secret: String,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	struct TestNestedConfig {
+		name: String,
+		server: TestServerConfig,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	struct TestServerConfig {
+		host: String,
+		port: u16,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	struct TestSecretConfig {
+		user: String,
+		#[configr(secret)]
+		token: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestFlattenConfig {
+		name: String,
+		#[serde(flatten)]
+		server: TestServerConfig,
+	}
+
+	#[cfg(feature = "schemars")]
+	#[derive(Configr, Deserialize, schemars::JsonSchema)]
+	struct TestSchemaConfig {
+		name: String,
+		port: u16,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Default, Debug, PartialEq)]
+	#[configr(default)]
+	struct TestGenericConfig<T> {
+		name: String,
+		value: T,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestClusterConfig {
+		name: String,
+		servers: Vec<TestServerConfig>,
+	}
+
+	/// Test config
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestDocConfig {
+		/// The first field
+		///
+		/// spanning lines
+		a: String,
+		b: String,
+		/// The server
+		server: TestServerConfig,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestTemplateDefaultConfig {
+		#[configr(default = "\"localhost\"")]
+		host: String,
+		#[configr(default = "8080")]
+		port: u16,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	#[configr(generate_default)]
+	struct TestGeneratedDefaultConfig {
+		#[configr(default = "\"localhost\"")]
+		host: String,
+		#[configr(default = "8080")]
+		port: u16,
+		name: Option<String>,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	struct TestUnitsConfig {
+		timeout: configr::HumanDuration,
+		max_size: configr::ByteSize,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestDeprecatedConfig {
+		#[serde(default)]
+		#[configr(deprecated = "use host instead")]
+		server: Option<String>,
+		host: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestHintConfig {
+		debug: bool,
+		path: std::path::PathBuf,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestCollectionConfig {
+		names: Vec<String>,
+		ports: std::collections::HashMap<String, u16>,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestFieldEnvConfig {
+		#[configr(env = "TESTCONFIG42_DATABASE_URL")]
+		url: String,
+		#[configr(env = "TESTCONFIG42_PORT")]
+		port: u16,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	struct TestExampleConfig {
+		/// The IRC server
+		#[configr(example = "irc.chat.twitch.tv")]
+		server: String,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	#[configr(app = "Test Config50")]
+	struct TestAppConfig {
+		a: String,
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	#[configr(file = "settings.toml")]
+	struct TestFileNameConfig {
+		a: String,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	struct TestOptionalConfig {
+		#[configr(default = "\"name\"")]
+		name: String,
+		nickname: Option<String>,
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	#[configr(validate = "validate_port")]
+	struct TestValidatedConfig {
+		#[configr(default = "0")]
+		port: u16,
+	}
+
+	fn validate_port(config: &TestValidatedConfig) -> Result<(), String> {
+		if config.port == 0 {
+			return Err("port must not be 0".to_string());
+		}
+		Ok(())
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	#[configr(version = 1, migrate = "migrate_username")]
+	struct TestMigratedConfig {
+		version: u32,
+		username: String,
+	}
+
+	fn migrate_username(
+		mut raw: toml::Value,
+		from_version: u32,
+	) -> toml::Value {
+		if from_version == 0 {
+			if let Some(table) = raw.as_table_mut() {
+				if let Some(name) = table.remove("name") {
+					table.insert("username".to_string(), name);
+				}
+			}
+		}
+		raw
+	}
+
+	#[derive(Configr, Deserialize, Debug, PartialEq)]
+	#[configr(version = 2, migrations = "chain_migrations")]
+	struct TestChainConfig {
+		version: u32,
+		username: String,
+		greeting: String,
+	}
+
+	fn chain_migrations() -> Vec<fn(toml::Value) -> toml::Value> { vec![rename_name, add_greeting] }
+
+	fn rename_name(raw: toml::Value) -> toml::Value { migrate_username(raw, 0) }
+
+	fn add_greeting(mut raw: toml::Value) -> toml::Value {
+		if let Some(table) = raw.as_table_mut() {
+			table.insert("greeting".to_string(), toml::Value::String("hi".to_string()));
+		}
+		raw
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, PartialEq)]
+	#[configr(default)]
+	struct TestUnserializableConfig {
+		map: std::collections::HashMap<u32, String>,
+	}
+
+	impl Default for TestUnserializableConfig {
+		fn default() -> Self {
+			TestUnserializableConfig {
+				map: vec![(1, "one".to_string())].into_iter().collect(),
+			}
+		}
+	}
+
+	/// Test default config
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	struct TestDocDefaultConfig {
+		/// The first field
+		a: String,
+		b: String,
+	}
+
+	#[cfg(feature = "json")]
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	#[configr(format = "json")]
+	struct TestJsonConfig {
+		a: String,
+		b: String,
+	}
+
+	#[cfg(feature = "ron")]
+	#[derive(Configr, Deserialize, Serialize, Debug, Default, PartialEq)]
+	#[configr(default)]
+	#[configr(format = "ron")]
+	struct TestRonConfig {
+		a: String,
+		b: Vec<u16>,
+	}
+
+	#[cfg(feature = "yaml")]
+	#[derive(Configr, Deserialize, Debug)]
+	#[configr(format = "yaml")]
+	struct TestYamlConfig {
+		a: Option<String>,
+		b: Option<String>,
+	}
+
+	#[derive(Deserialize, Debug)]
+	struct TestFailingTemplateConfig {
+		a: String,
+	}
+
+	impl Config<Self> for TestFailingTemplateConfig {
+		fn populate_template(_writer: impl std::io::Write) -> Result<(), ConfigError> {
+			Err(ConfigError::Template {
+				source: std::io::Error::new(std::io::ErrorKind::Other, "simulated write failure"),
+			})
+		}
+	}
+
+	#[derive(Configr, Deserialize, Serialize, Debug, Default)]
+	#[configr(default, current_dir)]
+	struct TestCurrentDirConfig {
+		a: String,
+	}
+
+	/// A uniquely named directory in the temporary directory, which
+	/// is removed again when dropped, for tests that shouldn't write
+	/// into the current directory
+	struct TestDir(std::path::PathBuf);
+
+	impl TestDir {
+		fn new(name: &str) -> Self {
+			let dir = std::env::temp_dir().join(format!("configr-{}-{}", name, std::process::id()));
+			std::fs::create_dir_all(&dir).unwrap();
+			TestDir(dir)
+		}
+
+		fn path(&self) -> &std::path::Path { &self.0 }
+	}
+
+	impl Drop for TestDir {
+		fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.0); }
+	}
+
+	/// The template generated for [`TestConfig`]
+	const TEMPLATE: &str = "# a (String, required, e.g. \"\")\na=\n# b (String, required, e.g. \"\")\nb=\n";
+
+	#[test]
+	fn generate_template_and_error() {
+		let dir = TestDir::new("test-config1");
+		let config = TestConfig::load_in_dir("Test Config1", dir.path());
+		// expect toml parse error with correct fields but no actual values
+		assert!(if let Err(e) = config {
+			if let ConfigError::Deserialize {
+				path,
+				toml,
+				source: _,
+			} = e
+			{
+				if path == dir.path().join("test-config1/config.toml") && toml == TEMPLATE {
+					true
+				} else {
+					false
+				}
+			} else {
+				false
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn current_dir_only_searched_when_opted_in() {
+		assert!(!TestDefaultConfig::CURRENT_DIR);
+		assert!(TestCurrentDirConfig::CURRENT_DIR);
+		assert!(!super::system_config_dirs(false).contains(&std::path::PathBuf::from("./")));
+		if !cfg!(target_family = "unix") {
+			assert_eq!(super::system_config_dirs(true), vec![std::path::PathBuf::from(
+				"./"
+			)]);
+		}
+	}
+
+	#[test]
+	fn read_proper_config() {
+		let dir = TestDir::new("test-config2");
+		std::fs::create_dir(dir.path().join("test-config2")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config2/config.toml"),
+			b"a=\"test\"\nb=\"test\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_in_dir("Test Config2", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "test".into(),
+				b: "test".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn default_serialized_config() {
+		let dir = TestDir::new("test-config3");
+		let config = TestDefaultConfig::load_in_dir("Test Config3", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig {
+				a: Default::default(),
+				b: Default::default(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn template_write_failure() {
+		let dir = TestDir::new("test-config4");
+		let config = TestFailingTemplateConfig::load_in_dir("Test Config4", dir.path());
+		// expect the simulated write failure to be returned instead of a
+		// panic
+		assert!(
+			if let Err(ConfigError::WriteTemplate { path, source: _ }) = config {
+				path == dir.path().join("test-config4/config.toml")
+			} else {
+				false
+			}
+		);
+	}
+
+	#[test]
+	fn save_and_reload_config() {
+		let dir = TestDir::new("test-config5");
+		let config = TestDefaultConfig {
+			a: "saved".into(),
+			b: "config".into(),
+		};
+		config.save_in_dir("Test Config5", dir.path()).unwrap();
+		let loaded = TestDefaultConfig::load_in_dir("Test Config5", dir.path());
+		assert!(if let Ok(c) = loaded { c == config } else { false });
+	}
+
+	#[test]
+	fn load_in_dir_keeps_path() {
+		let dir = TestDir::new("test-config6");
+		let config_dir = dir.path().to_path_buf();
+		let _ = TestDefaultConfig::load_in_dir("Test Config6", &config_dir);
+		assert_eq!(config_dir, dir.path().to_path_buf());
+	}
+
+	#[cfg(feature = "json")]
+	#[test]
+	fn json_default_config() {
+		let dir = TestDir::new("test-config7");
+		let config = TestJsonConfig::load_in_dir("Test Config7", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestJsonConfig::default()
+		} else {
+			false
+		});
+		assert!(dir.path().join("test-config7/config.json").exists());
+	}
+
+	#[cfg(feature = "ron")]
+	#[test]
+	fn ron_default_config() {
+		let dir = TestDir::new("test-config54");
+		let config = TestRonConfig::load_in_dir("Test Config54", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestRonConfig::default()
+		} else {
+			false
+		});
+		assert!(dir.path().join("test-config54/config.ron").exists());
+	}
+
+	#[cfg(feature = "yaml")]
+	#[test]
+	fn yaml_empty_template() {
+		let dir = TestDir::new("test-config8");
+		let config = TestYamlConfig::load_in_dir("Test Config8", dir.path());
+		// empty yaml keys are read as null
+		assert!(if let Ok(c) = config {
+			c.a.is_none() && c.b.is_none()
+		} else {
+			false
+		});
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config8/config.yaml")).unwrap(),
+			"a:\nb:\n"
+		);
+	}
+
+	#[test]
+	fn load_auto_existing_file() {
+		let dir = TestDir::new("test-config9");
+		std::fs::create_dir(dir.path().join("test-config9")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config9/config.toml"),
+			b"a=\"auto\"\nb=\"auto\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_auto_in_dir("Test Config9", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "auto".into(),
+				b: "auto".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[cfg(feature = "yaml")]
+	#[test]
+	fn load_auto_yml_file() {
+		let dir = TestDir::new("test-config10");
+		std::fs::create_dir(dir.path().join("test-config10")).unwrap();
+		std::fs::write(dir.path().join("test-config10/config.yml"), b"a: auto\nb: auto\n").unwrap();
+		let config = TestConfig::load_auto_in_dir("Test Config10", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "auto".into(),
+				b: "auto".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[cfg(feature = "yaml")]
+	#[test]
+	fn load_auto_follows_file_name() {
+		let dir = TestDir::new("test-config79");
+		std::fs::create_dir(dir.path().join("test-config79")).unwrap();
+		std::fs::write(dir.path().join("test-config79/config.yml"), b"a: ignored\n").unwrap();
+		std::fs::write(dir.path().join("test-config79/settings.yml"), b"a: auto\n").unwrap();
+		let config = TestFileNameConfig::load_auto_in_dir("Test Config79", dir.path());
+		assert_eq!(config.unwrap(), TestFileNameConfig { a: "auto".into() });
+	}
+
+	#[test]
+	fn custom_file_name() {
+		let dir = TestDir::new("test-config11");
+		let config =
+			TestDefaultConfig::load_with_file_name_in_dir("Test Config11", "settings.toml", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig::default()
+		} else {
+			false
+		});
+		assert!(dir.path().join("test-config11/settings.toml").exists());
+		assert!(!dir.path().join("test-config11/config.toml").exists());
+	}
+
+	#[test]
+	fn env_overrides_file() {
+		let dir = TestDir::new("test-config12");
+		std::env::set_var("CONFIGR_TEST12_A", "from env");
+		let config = TestDefaultConfig::load_with_env_in_dir("Test Config12", "configr_test12", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig {
+				a: "from env".into(),
+				b: Default::default(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn load_or_default_without_file() {
+		let dir = TestDir::new("test-config13");
+		let config = TestDefaultConfig::load_or_default_in_dir("Test Config13", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig::default()
+		} else {
+			false
+		});
+		// nothing should have been created on disk
+		assert!(!dir.path().join("test-config13").exists());
+	}
+
+	#[test]
+	fn load_returns_path() {
+		let dir = TestDir::new("test-config14");
+		let config = TestDefaultConfig::load_with_path_in_dir("Test Config14", dir.path());
+		assert!(if let Ok((c, path)) = config {
+			c == TestDefaultConfig::default()
+				&& path == std::fs::canonicalize(dir.path().join("test-config14/config.toml")).unwrap()
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn config_path_has_no_side_effects() {
+		let path = TestDefaultConfig::config_path("Test Config15", true).unwrap();
+		assert_eq!(
+			path,
+			dirs::config_dir()
+				.unwrap()
+				.join("test-config15")
+				.join("config.toml")
+		);
+		assert!(!path.exists());
+	}
+
+	#[test]
+	fn partial_file_uses_defaults() {
+		let dir = TestDir::new("test-config16");
+		std::fs::create_dir(dir.path().join("test-config16")).unwrap();
+		std::fs::write(dir.path().join("test-config16/config.toml"), b"a=\"partial\"\n").unwrap();
+		let config = TestDefaultConfig::load_in_dir("Test Config16", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig {
+				a: "partial".into(),
+				b: Default::default(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn nested_template_tables() {
+		let dir = TestDir::new("test-config17");
+		let _ = TestNestedConfig::load_in_dir("Test Config17", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config17/config.toml")).unwrap(),
+			"# name (String, required, e.g. \"\")\nname=\n\n[server]\n# host (String, required, e.g. \
+			 \"\")\nhost=\n# port (u16, required, e.g. 0)\nport=\n"
+		);
+	}
+
+	#[test]
+	fn dump_redacts_secrets() {
+		let config = TestSecretConfig {
+			user: "name".to_string(),
+			token: "hunter2".to_string(),
+		};
+		let mut dumped = Vec::new();
+		config.dump(&mut dumped, true).unwrap();
+		let dumped = String::from_utf8(dumped).unwrap();
+		assert!(dumped.contains("user = \"name\""));
+		assert!(dumped.contains("token = \"***\""));
+		assert!(!dumped.contains("hunter2"));
+
+		let mut dumped = Vec::new();
+		config.dump(&mut dumped, false).unwrap();
+		assert!(String::from_utf8(dumped).unwrap().contains("token = \"hunter2\""));
+	}
+
+	#[test]
+	fn secrets_redacted_from_errors() {
+		let error = TestSecretConfig::load_from_str("user = \"name\"\ntoken = hunter2\n").unwrap_err();
+		assert!(error.content().unwrap().contains("token = \"***\""));
+		assert!(error
+			.excerpt(1, ConfigError::EXCERPT_WIDTH)
+			.unwrap()
+			.contains("token = \"***\""));
+		assert!(!error.to_string().contains("hunter2"));
+		assert!(!format!("{:?}", error).contains("hunter2"));
+
+		let error = TestSecretConfig::load_from_str("user = 1\ntoken = \"hunter2\"\n").unwrap_err();
+		assert!(!error.to_string().contains("hunter2"));
+
+		let error = TestSecretConfig::load_from_str("user = \"name\"\ntoken = 1234\n").unwrap_err();
+		assert!(error.to_string().contains("integer `***`"));
+		assert!(!error.to_string().contains("1234"));
+	}
+
+	#[test]
+	fn short_secrets_keep_error_messages() {
+		let message = |token| {
+			TestSecretConfig::load_from_str(&format!("user = 2\ntoken = \"{}\"\n", token))
+				.unwrap_err()
+				.to_string()
+		};
+		assert_eq!(message("1"), message("unrelated"));
+		assert!(message("1").contains("line 1 column"));
+	}
+
+	#[test]
+	fn multi_line_secrets_redacted_from_errors() {
+		let error = TestSecretConfig::load_from_str("token = \"\"\"\nhunter2\nhunter3\"\"\"\nuser = 2\n")
+			.unwrap_err();
+		assert_eq!(error.content().unwrap(), "token = \"***\"\n\n\nuser = 2\n");
+		assert!(error
+			.excerpt(0, ConfigError::EXCERPT_WIDTH)
+			.unwrap()
+			.contains("user = 2"));
+		assert!(!format!("{:?}", error).contains("hunter"));
+	}
+
+	#[test]
+	fn flattened_fields_inlined() {
+		let dir = TestDir::new("test-config68");
+		let _ = TestFlattenConfig::load_in_dir("Test Config68", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config68/config.toml")).unwrap(),
+			"# name (String, required, e.g. \"\")\nname=\n# host (String, required, e.g. \"\")\nhost=\n# \
+			 port (u16, required, e.g. 0)\nport=\n"
+		);
+
+		std::fs::write(
+			dir.path().join("test-config68/config.toml"),
+			b"name=\"a\"\nhost=\"b\"\nport=1\n",
+		)
+		.unwrap();
+		let (_, keys) =
+			TestFlattenConfig::load_with_unknown_keys_in_dir("Test Config68", dir.path()).unwrap();
+		assert!(keys.is_empty());
+	}
+
+	#[test]
+	fn array_of_tables_template() {
+		let dir = TestDir::new("test-config62");
+		let _ = TestClusterConfig::load_in_dir("Test Config62", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config62/config.toml")).unwrap(),
+			"# name (String, required, e.g. \"\")\nname=\n\n# servers (Vec<TestServerConfig>, required)\n# \
+			 [[servers]]\n# host (String, required, e.g. \"\")\n# host=\n# port (u16, required, e.g. 0)\n# \
+			 port=\n"
+		);
+
+		std::fs::write(
+			dir.path().join("test-config62/config.toml"),
+			b"name=\"a\"\n[[servers]]\nhost=\"b\"\nport=1\nextra=2\n",
+		)
+		.unwrap();
+		let (_, keys) =
+			TestClusterConfig::load_with_unknown_keys_in_dir("Test Config62", dir.path()).unwrap();
+		assert_eq!(keys, vec!["servers.0.extra".to_string()]);
+	}
+
+	#[test]
+	fn skipped_fields_not_in_template() {
+		let dir = TestDir::new("test-config18");
+		let config = TestSkipDefaultConfig::load_in_dir("Test Config18", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestSkipDefaultConfig::default()
+		} else {
+			false
+		});
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config18/config.toml")).unwrap(),
+			"a = \"\"\n"
+		);
+		let _ = TestSkipConfig::load_in_dir("Test Config19", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config19/config.toml")).unwrap(),
+			"# a (String, required, e.g. \"\")\na=\n"
+		);
+	}
+
+	#[test]
+	fn renamed_template_keys() {
+		let dir = TestDir::new("test-config20");
+		let _ = TestRenameConfig::load_in_dir("Test Config20", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config20/config.toml")).unwrap(),
+			"# api-key (String, required, e.g. \"\")\napi-key=\n# Secret (String, required, e.g. \
+			 \"\")\nSecret=\n"
+		);
+	}
+
+	#[test]
+	fn doc_comments_in_template() {
+		let dir = TestDir::new("test-config21");
+		let _ = TestDocConfig::load_in_dir("Test Config21", dir.path());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config21/config.toml")).unwrap(),
+			"# Test config\n\n# The first field\n#\n# spanning lines\n# a (String, required, e.g. \
+			 \"\")\na=\n# b (String, required, e.g. \"\")\nb=\n\n# The server\n[server]\n# host (String, \
+			 required, e.g. \"\")\nhost=\n# port (u16, required, e.g. 0)\nport=\n"
+		);
+	}
+
+	#[test]
+	fn doc_comments_in_default_template() {
+		let dir = TestDir::new("test-config22");
+		let config = TestDocDefaultConfig::load_in_dir("Test Config22", dir.path()).unwrap();
+		assert_eq!(config, TestDocDefaultConfig::default());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config22/config.toml")).unwrap(),
+			"# Test default config\n\n# The first field\na = \"\"\nb = \"\"\n"
+		);
+	}
+
+	#[test]
+	fn template_default_values() {
+		let dir = TestDir::new("test-config23");
+		let config = TestTemplateDefaultConfig::load_in_dir("Test Config23", dir.path()).unwrap();
+		assert_eq!(config, TestTemplateDefaultConfig {
+			host: "localhost".to_string(),
+			port: 8080,
+		});
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config23/config.toml")).unwrap(),
+			"host=\"localhost\"\nport=8080\n"
+		);
+	}
+
+	#[test]
+	fn optional_fields_commented_out() {
+		let dir = TestDir::new("test-config24");
+		let config = TestOptionalConfig::load_in_dir("Test Config24", dir.path()).unwrap();
+		assert_eq!(config, TestOptionalConfig {
+			name: "name".to_string(),
+			nickname: None,
+		});
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config24/config.toml")).unwrap(),
+			"name=\"name\"\n# nickname = \n"
+		);
+	}
+
+	#[test]
+	fn validation_failure() {
+		let dir = TestDir::new("test-config25");
+		let config = TestValidatedConfig::load_in_dir("Test Config25", dir.path());
+		assert!(if let Err(ConfigError::Validation { message }) = config {
+			message == "port must not be 0"
+		} else {
+			false
+		});
+
+		std::fs::write(dir.path().join("test-config25/config.toml"), b"port=8080\n").unwrap();
+		let config = TestValidatedConfig::load_in_dir("Test Config25", dir.path());
+		assert!(if let Ok(c) = config { c.port == 8080 } else { false });
+	}
+
+	#[cfg(feature = "watch")]
+	#[test]
+	fn watch_reloads_on_change() {
+		let dir = TestDir::new("test-config26");
+		let (tx, rx) = std::sync::mpsc::channel();
+		let watcher = TestConfig::watch_in_dir("Test Config26", dir.path(), move |config| {
+			let _ = tx.send(config);
+		})
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config26/config.toml"),
+			b"a=\"watched\"\nb=\"watched\"\n",
+		)
+		.unwrap();
+		let config = rx.recv_timeout(std::time::Duration::from_secs(5));
+		assert!(if let Ok(Ok(c)) = config {
+			c == TestConfig {
+				a: "watched".to_string(),
+				b: "watched".to_string(),
+			}
+		} else {
+			false
+		});
+
+		drop(watcher);
+	}
+
+	#[cfg(feature = "watch")]
+	#[test]
+	fn watch_channel_sends_errors() {
+		let dir = TestDir::new("test-config65");
+		let (rx, watcher) = TestConfig::watch_channel_in_dir("Test Config65", dir.path()).unwrap();
+		std::fs::write(dir.path().join("test-config65/config.toml"), b"a=\n").unwrap();
+		let config = rx.recv_timeout(std::time::Duration::from_secs(5));
+		assert!(matches!(config, Ok(Err(ConfigError::Deserialize { .. }))));
+
+		drop(watcher);
+		// Reloads that were already queued are still received first
+		let closed = loop {
+			if let Err(error) = rx.recv_timeout(std::time::Duration::from_secs(5)) {
+				break error;
+			}
+		};
+		assert_eq!(closed, std::sync::mpsc::RecvTimeoutError::Disconnected);
+	}
+
+	#[test]
+	fn stale_after_modification() {
+		let dir = TestDir::new("test-config66");
+		let (_, modified) =
+			TestDefaultConfig::load_with_modified_in_dir("Test Config66", dir.path()).unwrap();
+		let modified = modified.unwrap();
+		assert!(!TestDefaultConfig::is_stale_in_dir("Test Config66", dir.path(), modified).unwrap());
+
+		// Leave enough time for filesystems with coarse modification times
+		std::thread::sleep(std::time::Duration::from_millis(1100));
+		std::fs::write(dir.path().join("test-config66/config.toml"), b"a=\"new\"\n").unwrap();
+		assert!(TestDefaultConfig::is_stale_in_dir("Test Config66", dir.path(), modified).unwrap());
+		assert!(TestDefaultConfig::is_stale_in_dir("Test Config67", dir.path(), modified).unwrap());
+	}
+
+	#[cfg(feature = "figment")]
+	#[test]
+	fn figment_provider_layers() {
+		let dir = TestDir::new("test-config63");
+		std::fs::create_dir(dir.path().join("test-config63")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config63/config.toml"),
+			b"a=\"file\"\nb=\"file\"\n",
+		)
+		.unwrap();
+		let config: TestDefaultConfig =
+			figment::Figment::from(TestDefaultConfig::provider_in_dir("Test Config63", dir.path()).unwrap())
+				.merge(figment::providers::Serialized::default("b", "override"))
+				.extract()
+				.unwrap();
+		assert_eq!(config, TestDefaultConfig {
+			a: "file".to_string(),
+			b: "override".to_string(),
+		});
+
+		let missing = TestDefaultConfig::provider_in_dir("Test Config64", dir.path()).unwrap();
+		assert!(figment::Provider::data(&missing).unwrap().is_empty());
+	}
+
+	#[cfg(feature = "tokio")]
+	#[tokio::test]
+	async fn load_in_dir_async_reads_file() {
+		let dir = TestDir::new("test-config27");
+		std::fs::create_dir(dir.path().join("test-config27")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config27/config.toml"),
+			b"a=\"async\"\nb=\"async\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_in_dir_async("Test Config27", dir.path()).await;
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "async".to_string(),
+				b: "async".to_string(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn load_from_str_without_files() {
+		let config = TestConfig::load_from_str("a=\"str\"\nb=\"str\"\n");
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "str".to_string(),
+				b: "str".to_string(),
+			}
+		} else {
+			false
+		});
+
+		let config = TestConfig::load_from_str("a=\n");
+		assert!(if let Err(ConfigError::Deserialize { path, .. }) = config {
+			path == std::path::Path::new(configr::STR_PATH)
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn load_from_reader_without_files() {
+		let config = TestConfig::load_from_reader(&b"a=\"reader\"\nb=\"reader\"\n"[..]);
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "reader".to_string(),
+				b: "reader".to_string(),
+			}
+		} else {
+			false
+		});
+
+		let config = TestConfig::load_from_reader(&[0xff, 0xfe][..]);
+		assert!(matches!(config, Err(ConfigError::ReadStream { .. })));
+	}
+
+	#[test]
+	fn generic_config() {
+		let config = TestGenericConfig::<u16>::load_from_str("name = \"generic\"\n").unwrap();
+		assert_eq!(config, TestGenericConfig {
+			name: "generic".to_string(),
+			value: 0,
+		});
+		let config = TestGenericConfig::<Vec<String>>::load_from_str("value = [\"a\"]\n").unwrap();
+		assert_eq!(config.value, vec!["a".to_string()]);
+	}
+
+	#[test]
+	fn migrate_old_version() {
+		let config = TestMigratedConfig::load_from_str("name=\"old\"\n");
+		assert!(if let Ok(c) = config {
+			c == TestMigratedConfig {
+				version: 1,
+				username: "old".to_string(),
+			}
+		} else {
+			false
+		});
+
+		let config = TestMigratedConfig::load_from_str("version=1\nusername=\"new\"\n");
+		assert!(if let Ok(c) = config {
+			c.username == "new"
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn migration_chain_and_downgrade() {
+		let config = TestChainConfig::load_from_str("name=\"old\"\n");
+		assert!(if let Ok(c) = config {
+			c == TestChainConfig {
+				version: 2,
+				username: "old".to_string(),
+				greeting: "hi".to_string(),
+			}
+		} else {
+			false
+		});
+
+		let config = TestChainConfig::load_from_str("version=3\nusername=\"new\"\ngreeting=\"yo\"\n");
+		assert!(matches!(
+			config,
+			Err(ConfigError::NewerVersion {
+				version: 3,
+				supported: 2,
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn profile_overrides_base() {
+		let dir = TestDir::new("test-config28");
+		std::fs::create_dir(dir.path().join("test-config28")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config28/config.toml"),
+			b"a=\"base\"\nb=\"base\"\n",
+		)
+		.unwrap();
+		std::fs::write(dir.path().join("test-config28/dev.toml"), b"b=\"dev\"\n").unwrap();
+		let config = TestConfig::load_profile_in_dir("Test Config28", "dev", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "base".to_string(),
+				b: "dev".to_string(),
+			}
+		} else {
+			false
+		});
+
+		// a missing profile falls back to the base config
+		let config = TestConfig::load_profile_in_dir("Test Config28", "prod", dir.path());
+		assert!(if let Ok(c) = config { c.b == "base" } else { false });
+		assert!(!dir.path().join("test-config28/prod.toml").exists());
+	}
+
+	#[test]
+	fn profile_cannot_escape_app_dir() {
+		let dir = TestDir::new("test-config77");
+		for profile in &["../other/config", "/etc/passwd", "..", ""] {
+			assert!(matches!(
+				TestConfig::load_profile_in_dir("Test Config77", profile, dir.path()),
+				Err(ConfigError::InvalidProfile { .. })
+			));
+		}
+		assert!(!dir.path().join("test-config77").exists());
+	}
+
+	#[test]
+	fn load_with_dirs_precedence() {
+		let dir = TestDir::new("test-config29");
+		std::fs::create_dir_all(dir.path().join("test-config29/first")).unwrap();
+		std::fs::create_dir_all(dir.path().join("test-config29/second/test-config29")).unwrap();
+		std::fs::create_dir_all(dir.path().join("test-config29/third/test-config29")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config29/second/test-config29/config.toml"),
+			b"a=\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config29/third/test-config29/config.toml"),
+			b"a=\"third\"\nb=\"third\"\n",
+		)
+		.unwrap();
+		let dirs = ["first", "second", "third"]
+			.iter()
+			.map(|d| dir.path().join("test-config29").join(d))
+			.collect::<Vec<_>>();
+		// the first dir has no file and the second an invalid one
+		let config = TestConfig::load_with_dirs("Test Config29", &dirs);
+		assert!(if let Ok(c) = config { c.a == "third" } else { false });
+
+		let config = TestConfig::load_with_dirs("Test Config29", &dirs[..2]);
+		assert!(if let Err(ConfigError::NoValidConfig { paths }) = config {
+			paths.len() == 2
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn load_merged_user_over_system() {
+		let dir = TestDir::new("test-config30");
+		std::fs::create_dir_all(dir.path().join("test-config30/system/test-config30")).unwrap();
+		std::fs::create_dir_all(dir.path().join("test-config30/user/test-config30")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config30/system/test-config30/config.toml"),
+			b"a=\"system\"\nb=\"system\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config30/user/test-config30/config.toml"),
+			b"b=\"user\"\n",
+		)
+		.unwrap();
+		let dirs = ["system", "user"]
+			.iter()
+			.map(|d| dir.path().join("test-config30").join(d))
+			.collect::<Vec<_>>();
+		let config = TestConfig::load_merged_in_dirs("Test Config30", &dirs);
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "system".to_string(),
+				b: "user".to_string(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn precedence_between_system_and_user() {
+		let dir = TestDir::new("test-config73");
+		let system = dir.path().join("system");
+		let user = dir.path().join("user");
+		std::fs::create_dir_all(system.join("test-config73")).unwrap();
+		std::fs::write(
+			system.join("test-config73/config.toml"),
+			b"a=\"system\"\nb=\"system\"\n",
+		)
+		.unwrap();
+		let load = |precedence| {
+			TestConfig::load_with_precedence_in_dirs("Test Config73", precedence, &[system.clone()], &user)
+				.unwrap()
+		};
+		assert_eq!(load(configr::Precedence::UserFirst).a, "system");
+
+		std::fs::create_dir_all(user.join("test-config73")).unwrap();
+		std::fs::write(
+			user.join("test-config73/config.toml"),
+			b"a=\"user\"\nb=\"user\"\n",
+		)
+		.unwrap();
+		assert_eq!(load(configr::Precedence::default()).a, "user");
+		assert_eq!(load(configr::Precedence::SystemFirst).a, "system");
+		assert_eq!(load(configr::Precedence::Merge).a, "user");
+	}
+
+	#[test]
+	fn runtime_defaults_under_file() {
+		let dir = TestDir::new("test-config74");
+		std::fs::create_dir_all(dir.path().join("test-config74")).unwrap();
+		std::fs::write(dir.path().join("test-config74/config.toml"), b"a=\"file\"\n").unwrap();
+		let defaults = toml::from_str("a=\"runtime\"\nb=\"runtime\"\n").unwrap();
+		let config = TestConfig::load_with_defaults_in_dir("Test Config74", dir.path(), defaults);
+		assert_eq!(config.unwrap(), TestConfig {
+			a: "file".to_string(),
+			b: "runtime".to_string(),
+		});
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn created_file_is_private() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let dir = TestDir::new("test-config31");
+		let _ = TestConfig::load_in_dir("Test Config31", dir.path());
+		let mode = std::fs::metadata(dir.path().join("test-config31/config.toml"))
+			.unwrap()
+			.permissions()
+			.mode();
+		assert_eq!(mode & 0o077, 0);
+	}
+
+	#[test]
+	fn deserialize_error_shows_only_failing_line() {
+		let config = TestConfig::load_from_str("a=\"secret\"\nb=\n");
+		assert!(if let Err(e) = config {
+			let message = e.to_string();
+			!message.contains("secret") && message.contains("```\nb=\n")
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn missing_file_is_not_found() {
+		let dir = TestDir::new("test-config32");
+		let config = TestConfig::load_no_create_in_dir("Test Config32", dir.path());
+		assert!(matches!(config, Err(ConfigError::NotFound { .. })));
+		assert!(!dir.path().join("test-config32").exists());
+	}
+
+	#[test]
+	fn template_string_without_files() {
+		assert_eq!(TestConfig::template_string().unwrap(), TEMPLATE);
+		assert!(TestFailingTemplateConfig::template_string().is_err());
+	}
+
+	#[test]
+	fn template_type_hints() {
+		assert_eq!(
+			TestHintConfig::template_string().unwrap(),
+			"# debug (bool, required, true or false)\ndebug=\n# path (std::path::PathBuf, required)\npath=\n"
+		);
+	}
+
+	#[cfg(feature = "schemars")]
+	#[test]
+	fn schema_describes_fields() {
+		let schema = TestSchemaConfig::schema();
+		assert_eq!(schema["properties"]["name"]["type"], "string");
+		assert_eq!(schema["properties"]["port"]["type"], "integer");
+		assert_eq!(schema["required"], serde_json::json!(["name", "port"]));
+	}
+
+	#[test]
+	fn collection_template_scaffolding() {
+		let template = TestCollectionConfig::template_string().unwrap();
+		assert_eq!(
+			template,
+			"names=[]\n\n# ports (std::collections::HashMap<String, u16>)\n[ports]\n"
+		);
+		assert!(if let Ok(c) = TestCollectionConfig::load_from_str(&template) {
+			c.names.is_empty() && c.ports.is_empty()
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn reject_path_traversal() {
+		let dir = TestDir::new("test-config84");
+		for app_name in &["../../etc", "foo/bar", "foo\\bar", ""] {
+			let config = TestConfig::load_in_dir(app_name, dir.path());
+			assert!(matches!(config, Err(ConfigError::InvalidAppName { .. })));
+		}
+	}
+
+	#[test]
+	fn unserializable_default_template() {
+		let dir = TestDir::new("test-config33");
+		// toml can't have integer keys, which used to panic
+		let config = TestUnserializableConfig::load_in_dir("Test Config33", dir.path());
+		assert!(matches!(config, Err(ConfigError::Serialize { .. })));
+	}
+
+	#[test]
+	fn env_override_path() {
+		let dir = TestDir::new("test-config34");
+		std::fs::create_dir(dir.path().join("test-config34")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config34/custom.toml"),
+			b"a=\"override\"\nb=\"override\"\n",
+		)
+		.unwrap();
+		std::env::set_var(
+			"TEST_CONFIG34_CONFIG",
+			dir.path().join("test-config34/custom.toml"),
+		);
+		let config = TestConfig::load_with_env_override("Test Config34", "TEST_CONFIG34_CONFIG", true);
+		assert!(if let Ok(c) = config {
+			c.a == "override"
+		} else {
+			false
+		});
+
+		std::env::set_var(
+			"TEST_CONFIG34_CONFIG",
+			dir.path().join("test-config34/missing.toml"),
+		);
+		let config = TestConfig::load_with_env_override("Test Config34", "TEST_CONFIG34_CONFIG", true);
+		assert!(matches!(config, Err(ConfigError::NotFound { .. })));
+
+		std::env::remove_var("TEST_CONFIG34_CONFIG");
+	}
+
+	#[test]
+	fn load_from_exact_path() {
+		let dir = TestDir::new("test-config35");
+		std::fs::create_dir(dir.path().join("test-config35")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config35/exact.conf"),
+			b"a=\"exact\"\nb=\"exact\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_from_path(dir.path().join("test-config35/exact.conf"));
+		assert!(if let Ok(c) = config { c.b == "exact" } else { false });
+
+		let config = TestConfig::load_from_path(dir.path().join("test-config35/missing.toml"));
+		assert!(matches!(config, Err(ConfigError::NotFound { .. })));
+		assert!(!dir.path().join("test-config35/missing.toml").exists());
+	}
+
+	#[test]
+	fn save_leaves_no_temp_file() {
+		let dir = TestDir::new("test-config36");
+		let config = TestDefaultConfig {
+			a: "atomic".to_string(),
+			b: "atomic".to_string(),
+		};
+		config.save_in_dir("Test Config36", dir.path()).unwrap();
+		config.save_in_dir("Test Config36", dir.path()).unwrap();
+		let files = std::fs::read_dir(dir.path().join("test-config36"))
+			.unwrap()
+			.count();
+		assert_eq!(files, 1);
+		assert!(
+			if let Ok(c) = TestDefaultConfig::load_in_dir("Test Config36", dir.path()) {
+				c == config
+			} else {
+				false
+			}
+		);
+	}
+
+	#[test]
+	fn unknown_keys_reported() {
+		let dir = TestDir::new("test-config37");
+		std::fs::create_dir(dir.path().join("test-config37")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config37/config.toml"),
+			b"name=\"n\"\nnmae=\"typo\"\n[server]\nhost=\"h\"\nport=1\nhsot=\"typo\"\n",
+		)
+		.unwrap();
+		let config = TestNestedConfig::load_with_unknown_keys_in_dir("Test Config37", dir.path());
+		assert!(if let Ok((c, keys)) = config {
+			c.server.port == 1 && keys == vec!["nmae".to_string(), "server.hsot".to_string()]
+		} else {
+			false
+		});
+
+		let config = TestNestedConfig::load_strict_in_dir("Test Config37", dir.path());
+		assert!(matches!(config, Err(ConfigError::UnknownKeys { .. })));
+	}
+
+	#[test]
+	fn save_preserves_comments() {
+		let dir = TestDir::new("test-config38");
+		std::fs::create_dir(dir.path().join("test-config38")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config38/config.toml"),
+			b"# Written by hand\nb = \"old\" # kept\n\n# The first key\na = \"same\"\n",
+		)
+		.unwrap();
+		let config = TestDefaultConfig {
+			a: "same".to_string(),
+			b: "new".to_string(),
+		};
+		config.save_in_dir("Test Config38", dir.path()).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config38/config.toml")).unwrap(),
+			"# Written by hand\nb = \"new\" # kept\n\n# The first key\na = \"same\"\n"
+		);
+	}
+
+	#[test]
+	fn sync_template_adds_missing_keys() {
+		let dir = TestDir::new("test-config70");
+		std::fs::create_dir(dir.path().join("test-config70")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config70/config.toml"),
+			b"# mine\nb = \"kept\"\n",
+		)
+		.unwrap();
+		let added = TestDefaultConfig::sync_template_in_dir("Test Config70", dir.path()).unwrap();
+		assert_eq!(added, vec!["a".to_string()]);
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config70/config.toml")).unwrap(),
+			"# mine\nb = \"kept\"\na=\"\"\n"
+		);
+		assert!(
+			TestDefaultConfig::sync_template_in_dir("Test Config70", dir.path())
+				.unwrap()
+				.is_empty()
+		);
+
+		std::fs::create_dir(dir.path().join("test-config71")).unwrap();
+		std::fs::write(dir.path().join("test-config71/config.toml"), b"name=\"n\"\n").unwrap();
+		let added = TestNestedConfig::sync_template_in_dir("Test Config71", dir.path()).unwrap();
+		assert_eq!(added, vec!["server".to_string()]);
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config71/config.toml")).unwrap(),
+			"name=\"n\"\n\n[server]\n# host = \n# port = \n"
+		);
+	}
+
+	#[test]
+	fn write_atomic_skips_existing_temp_files() {
+		let dir = TestDir::new("test-config81");
+		let path = dir.path().join("config.toml");
+		let next = configr::TEMP_FILE_COUNTER.load(std::sync::atomic::Ordering::Relaxed);
+		let taken: Vec<_> = (next..next + 5)
+			.map(|n| {
+				dir.path()
+					.join(format!(".config.toml.tmp-{}-{}", std::process::id(), n))
+			})
+			.collect();
+		for tmp in &taken {
+			std::fs::write(tmp, b"taken").unwrap();
+		}
+
+		configr::write_atomic(&path, b"a=\"new\"\n", 0o600).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "a=\"new\"\n");
+		for tmp in &taken {
+			assert_eq!(std::fs::read_to_string(tmp).unwrap(), "taken");
+		}
+	}
+
+	#[test]
+	fn save_keeps_crlf_line_endings() {
+		let dir = TestDir::new("test-config69");
+		std::fs::create_dir(dir.path().join("test-config69")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config69/config.toml"),
+			b"# Written on Windows\r\nb = \"old\" # kept\r\n",
+		)
+		.unwrap();
+		let config = TestDefaultConfig::load_in_dir("Test Config69", dir.path()).unwrap();
+		assert_eq!(config.b, "old");
+
+		let config = TestDefaultConfig {
+			a: "added".to_string(),
+			b: "new".to_string(),
+		};
+		config.save_in_dir("Test Config69", dir.path()).unwrap();
+		let content = std::fs::read_to_string(dir.path().join("test-config69/config.toml")).unwrap();
+		assert!(content.starts_with("# Written on Windows\r\nb = \"new\" # kept\r\n"));
+		assert!(content.contains("\"added\""));
+		assert_eq!(content.matches('\n').count(), content.matches("\r\n").count());
+		assert_eq!(
+			TestDefaultConfig::load_in_dir("Test Config69", dir.path()).unwrap(),
+			config
+		);
+	}
+
+	#[test]
+	fn diff_from_default_config() {
+		let config = TestDefaultConfig {
+			a: "changed".to_string(),
+			b: String::new(),
+		};
+		assert_eq!(config.diff_from_default().unwrap(), vec![(
+			"a".to_string(),
+			Some(toml::Value::String(String::new())),
+			toml::Value::String("changed".to_string())
+		)]);
+		assert!(TestDefaultConfig::default()
+			.diff_from_default()
+			.unwrap()
+			.is_empty());
+	}
+
+	#[test]
+	fn save_minimal_omits_defaults() {
+		let dir = TestDir::new("test-config76");
+		let config = TestDefaultConfig {
+			a: "changed".to_string(),
+			b: String::new(),
+		};
+		config.save_minimal_in_dir("Test Config76", dir.path()).unwrap();
+		let path = dir.path().join("test-config76/config.toml");
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "a = \"changed\"\n");
+		assert_eq!(
+			TestDefaultConfig::load_in_dir("Test Config76", dir.path()).unwrap(),
+			config
+		);
+
+		std::fs::write(&path, b"# comment\na = \"changed\"\nb = \"changed\"\n").unwrap();
+		config.save_minimal_in_dir("Test Config76", dir.path()).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(&path).unwrap(),
+			"# comment\na = \"changed\"\n"
+		);
+	}
+
+	#[test]
+	fn loader_with_options() {
+		let dir = TestDir::new("test-config39");
+		let config = TestDefaultConfig::loader()
+			.app_name("Test Config39")
+			.config_dir(dir.path())
+			.file_name("custom.toml")
+			.create_if_missing(false)
+			.load();
+		assert!(matches!(config, Err(ConfigError::NotFound { .. })));
+		assert!(!dir.path().join("test-config39").exists());
+
+		std::env::set_var("TESTCONFIG39_B", "from env");
+		let config = TestDefaultConfig::loader()
+			.app_name("Test Config39")
+			.config_dir(dir.path())
+			.file_name("custom.toml")
+			.env_prefix("TESTCONFIG39")
+			.load();
+		assert!(if let Ok(c) = config {
+			c == TestDefaultConfig {
+				a: String::new(),
+				b: "from env".to_string(),
+			}
+		} else {
+			false
+		});
+		assert!(dir.path().join("test-config39/custom.toml").exists());
+
+		std::env::remove_var("TESTCONFIG39_B");
+	}
+
+	#[test]
+	fn loader_exact_app_name() {
+		let dir = TestDir::new("test-config83");
+		let config = TestDefaultConfig::loader()
+			.app_name("Test Config83")
+			.normalize_app_name(false)
+			.config_dir(dir.path())
+			.load();
+		assert!(config.is_ok());
+		assert!(dir.path().join("Test Config83/config.toml").exists());
+		assert!(!dir.path().join("test-config83").exists());
+
+		let config = TestDefaultConfig::loader()
+			.app_name("../Test Config83")
+			.normalize_app_name(false)
+			.config_dir(dir.path())
+			.load();
+		assert!(matches!(config, Err(ConfigError::InvalidAppName { .. })));
+	}
+
+	#[test]
+	fn loader_clone_without_clone_config() {
+		let dir = TestDir::new("test-config80");
+		std::fs::create_dir(dir.path().join("test-config80")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config80/config.toml"),
+			b"a=\"clone\"\nb=\"clone\"\n",
+		)
+		.unwrap();
+		let loader = TestConfig::loader()
+			.app_name("Test Config80")
+			.config_dir(dir.path());
+		let config = TestConfig {
+			a: "clone".into(),
+			b: "clone".into(),
+		};
+		assert_eq!(loader.clone().load().unwrap(), config);
+		assert_eq!(loader.load().unwrap(), config);
+	}
+
+	#[test]
+	fn loader_name_normalizer() {
+		let dir = TestDir::new("test-config72");
+		let config = TestDefaultConfig::loader()
+			.app_name("Test Config72")
+			.with_name_normalizer(|name| name.replace(' ', "_"))
+			.config_dir(dir.path())
+			.load();
+		assert!(config.is_ok());
+		assert!(dir.path().join("Test_Config72/config.toml").exists());
+
+		let config = TestDefaultConfig::loader()
+			.app_name("Test Config72")
+			.with_name_normalizer(|_| "..".to_string())
+			.config_dir(dir.path())
+			.load();
+		assert!(matches!(config, Err(ConfigError::InvalidAppName { .. })));
+	}
+
+	#[test]
+	fn drop_ins_merged_in_order() {
+		let dir = TestDir::new("test-config40");
+		std::fs::create_dir_all(dir.path().join("test-config40/config.d")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config40/config.toml"),
+			b"a=\"base\"\nb=\"base\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config40/config.d/20-second.toml"),
+			b"b=\"second\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config40/config.d/10-first.toml"),
+			b"a=\"first\"\nb=\"first\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config40/config.d/30-ignored.txt"),
+			b"a=\"ignored\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_with_drop_ins_in_dir("Test Config40", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "first".into(),
+				b: "second".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn local_override_merged() {
+		let dir = TestDir::new("test-config41");
+		std::fs::create_dir(dir.path().join("test-config41")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config41/config.toml"),
+			b"a=\"shared\"\nb=\"shared\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_with_local_override_in_dir("Test Config41", dir.path());
+		assert!(if let Ok(c) = config {
+			c.a == "shared" && c.b == "shared"
+		} else {
+			false
+		});
+
+		std::fs::write(
+			dir.path().join("test-config41/config.local.toml"),
+			b"b=\"local\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_with_local_override_in_dir("Test Config41", dir.path());
+		assert!(if let Ok(c) = config {
+			c.a == "shared" && c.b == "local"
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn local_override_follows_file_name() {
+		let dir = TestDir::new("test-config78");
+		std::fs::create_dir(dir.path().join("test-config78")).unwrap();
+		std::fs::write(dir.path().join("test-config78/settings.toml"), b"a=\"shared\"\n").unwrap();
+		std::fs::write(
+			dir.path().join("test-config78/config.local.toml"),
+			b"a=\"ignored\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config78/settings.local.toml"),
+			b"a=\"local\"\n",
+		)
+		.unwrap();
+		let config = TestFileNameConfig::load_with_local_override_in_dir("Test Config78", dir.path());
+		assert_eq!(config.unwrap(), TestFileNameConfig {
+			a: "local".to_string()
+		});
+	}
+
+	#[test]
+	fn field_env_overrides_file() {
+		let dir = TestDir::new("test-config42");
+		std::fs::create_dir(dir.path().join("test-config42")).unwrap();
+		std::fs::write(dir.path().join("test-config42/config.toml"), b"port=1\n").unwrap();
+		std::env::set_var("TESTCONFIG42_DATABASE_URL", "1234");
+		let config = TestFieldEnvConfig::load_with_field_env_in_dir("Test Config42", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestFieldEnvConfig {
+				url: "1234".to_string(),
+				port: 1,
+			}
+		} else {
+			false
+		});
+
+		std::env::set_var("TESTCONFIG42_PORT", "8080");
+		let config = TestFieldEnvConfig::load_with_field_env_in_dir("Test Config42", dir.path());
+		assert!(if let Ok(c) = config { c.port == 8080 } else { false });
+
+		std::env::remove_var("TESTCONFIG42_DATABASE_URL");
+		std::env::remove_var("TESTCONFIG42_PORT");
+	}
+
+	#[test]
+	fn reset_overwrites_with_default() {
+		let dir = TestDir::new("test-config43");
+		std::fs::create_dir(dir.path().join("test-config43")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config43/config.toml"),
+			b"a=\"changed\"\nb=\"changed\"\n",
+		)
+		.unwrap();
+		TestDefaultConfig::reset_to_default_in_dir("Test Config43", dir.path()).unwrap();
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config43/config.toml")).unwrap(),
+			TestDefaultConfig::template_string().unwrap()
+		);
+		assert!(
+			if let Ok(c) = TestDefaultConfig::load_in_dir("Test Config43", dir.path()) {
+				c == TestDefaultConfig::default()
+			} else {
+				false
+			}
+		);
+	}
+
+	#[test]
+	fn delete_removes_file_and_empty_dir() {
+		let dir = TestDir::new("test-config44");
+		TestDefaultConfig::load_in_dir("Test Config44", dir.path()).unwrap();
+		TestDefaultConfig::delete_in_dir("Test Config44", dir.path()).unwrap();
+		assert!(!dir.path().join("test-config44").exists());
+		// Deleting again is fine
+		TestDefaultConfig::delete_in_dir("Test Config44", dir.path()).unwrap();
+
+		std::fs::create_dir(dir.path().join("test-config44")).unwrap();
+		std::fs::write(dir.path().join("test-config44/config.toml"), b"").unwrap();
+		std::fs::write(dir.path().join("test-config44/dev.toml"), b"").unwrap();
+		TestDefaultConfig::delete_in_dir("Test Config44", dir.path()).unwrap();
+		assert!(!dir.path().join("test-config44/config.toml").exists());
+		assert!(dir.path().join("test-config44/dev.toml").exists());
+	}
+
+	#[test]
+	fn shared_config_reload() {
+		let dir = TestDir::new("test-config45");
+		std::fs::create_dir(dir.path().join("test-config45")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config45/config.toml"),
+			b"a=\"old\"\nb=\"old\"\n",
+		)
+		.unwrap();
+		let shared =
+			configr::SharedConfig::new(TestConfig::load_in_dir("Test Config45", dir.path()).unwrap());
+		let handle = shared.clone();
+		assert_eq!(handle.get().a, "old");
+
+		std::fs::write(
+			dir.path().join("test-config45/config.toml"),
+			b"a=\"new\"\nb=\"new\"\n",
+		)
+		.unwrap();
+		shared.reload_in_dir("Test Config45", dir.path()).unwrap();
+		assert_eq!(handle.get().a, "new");
+
+		std::fs::write(dir.path().join("test-config45/config.toml"), b"a=").unwrap();
+		assert!(shared.reload_in_dir("Test Config45", dir.path()).is_err());
+		assert_eq!(handle.get().a, "new");
+	}
+
+	#[test]
+	fn example_values_in_template() {
+		let dir = TestDir::new("test-config46");
+		let config = TestExampleConfig::load_in_dir("Test Config46", dir.path()).unwrap();
+		assert_eq!(config, TestExampleConfig::default());
+		assert_eq!(
+			std::fs::read_to_string(dir.path().join("test-config46/config.toml")).unwrap(),
+			"# The IRC server\n# example: irc.chat.twitch.tv\nserver = \"\"\n"
+		);
+	}
+
+	#[test]
+	fn env_nested_keys() {
+		let dir = TestDir::new("test-config47");
+		std::fs::create_dir(dir.path().join("test-config47")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config47/config.toml"),
+			b"name=\"n\"\n[server]\nhost=\"h\"\nport=1\n",
+		)
+		.unwrap();
+		std::env::set_var("TESTCONFIG47_SERVER__PORT", "5432");
+		let config = TestNestedConfig::load_with_env_in_dir("Test Config47", "testconfig47", dir.path());
+		assert!(if let Ok(c) = config {
+			c.server.host == "h" && c.server.port == 5432
+		} else {
+			false
+		});
+
+		std::env::remove_var("TESTCONFIG47_SERVER__PORT");
+	}
+
+	#[test]
+	fn from_env_without_file() {
+		std::env::set_var("TESTCONFIG75_NAME", "5");
+		std::env::set_var("TESTCONFIG75_SERVER__HOST", "localhost");
+		std::env::set_var("TESTCONFIG75_SERVER__PORT", "8080");
+		let config = TestNestedConfig::from_env("testconfig75").unwrap();
+		assert_eq!(config.name, "5");
+		assert_eq!(config.server.host, "localhost");
+		assert_eq!(config.server.port, 8080);
+
+		std::env::remove_var("TESTCONFIG75_SERVER__HOST");
+		std::env::remove_var("TESTCONFIG75_SERVER__PORT");
+		let error = TestNestedConfig::from_env("testconfig75").unwrap_err();
+		assert!(
+			matches!(&error, ConfigError::MissingEnv { keys, .. } if keys == &["server.host", "server.port"])
+		);
+		assert!(error
+			.to_string()
+			.contains("TESTCONFIG75_SERVER__HOST, TESTCONFIG75_SERVER__PORT"));
+		std::env::remove_var("TESTCONFIG75_NAME");
+	}
+
+	#[test]
+	fn env_config_path_var_skipped() {
+		let mut value = toml::from_str("name=\"n\"").unwrap();
+		configr::env::apply_vars(&mut value, "testconfig82", vec![
+			(
+				"TESTCONFIG82_CONFIG".to_string(),
+				"/etc/app/config.toml".to_string(),
+			),
+			("TESTCONFIG82_NAME".to_string(), "env".to_string()),
+		]);
+		assert_eq!(value, toml::from_str::<toml::Value>("name=\"env\"").unwrap());
+	}
+
+	#[test]
+	fn search_paths_in_order() {
+		let paths = TestConfig::search_paths("Test Config48", false).unwrap();
+		assert!(paths.len() > 1);
+		assert_eq!(
+			paths.last(),
+			TestConfig::search_paths("Test Config48", true).unwrap().first()
+		);
+		assert!(paths.iter().all(|p| p.ends_with("test-config48/config.toml")));
+	}
+
+	#[cfg(feature = "dotenv")]
+	#[test]
+	fn dotenv_below_environment() {
+		let dir = TestDir::new("test-config55");
+		std::fs::create_dir(dir.path().join("test-config55")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config55/config.toml"),
+			b"a=\"file\"\nb=\"file\"\n",
+		)
+		.unwrap();
+		std::fs::write(
+			dir.path().join("test-config55/.env"),
+			b"# secrets\nexport TESTCONFIG55_A=\"dotenv\"\nTESTCONFIG55_B=dotenv\n",
+		)
+		.unwrap();
+		std::env::set_var("TESTCONFIG55_B", "env");
+		let config = TestConfig::load_with_dotenv_in_dir("Test Config55", "testconfig55", dir.path());
+		std::env::remove_var("TESTCONFIG55_B");
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "dotenv".into(),
+				b: "env".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn generated_default_from_field_defaults() {
+		let dir = TestDir::new("test-config56");
+		let config = TestGeneratedDefaultConfig::load_in_dir("Test Config56", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestGeneratedDefaultConfig {
+				host: "localhost".into(),
+				port: 8080,
+				name: None,
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn merge_nested_tables() {
+		let mut config = TestNestedConfig {
+			name: "base".into(),
+			server: TestServerConfig {
+				host: "localhost".into(),
+				port: 1,
+			},
+		};
+		config
+			.merge(TestNestedConfig {
+				name: "overlay".into(),
+				server: TestServerConfig {
+					host: "example.com".into(),
+					port: 2,
+				},
+			})
+			.unwrap();
+		assert_eq!(config.name, "overlay");
+		assert_eq!(config.server, TestServerConfig {
+			host: "example.com".into(),
+			port: 2,
+		});
+	}
+
+	#[test]
+	fn long_lines_elided_in_errors() {
+		let content = format!("a=\"{}\" b\n", "x".repeat(2000));
+		let error = TestConfig::load_from_str(&content).unwrap_err();
+		let excerpt = error.excerpt(0, 20).unwrap();
+		assert!(excerpt.starts_with("```\n..."));
+		assert!(excerpt.lines().all(|l| l.len() <= 26));
+		assert!(error.to_string().len() < content.len());
+		assert_eq!(error.content(), Some(content.as_str()));
+	}
+
+	#[test]
+	fn byte_order_mark_stripped() {
+		let dir = TestDir::new("test-config57");
+		std::fs::create_dir(dir.path().join("test-config57")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config57/config.toml"),
+			"\u{feff}a=\"bom\"\nb=\"bom\"\n",
+		)
+		.unwrap();
+		let config = TestConfig::load_in_dir("Test Config57", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestConfig {
+				a: "bom".into(),
+				b: "bom".into(),
+			}
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn load_single_section() {
+		let dir = TestDir::new("test-config58");
+		std::fs::create_dir(dir.path().join("test-config58")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config58/config.toml"),
+			b"name=\"other\"\n[server]\nhost=\"h\"\nport=1\n",
+		)
+		.unwrap();
+		let config = TestServerConfig::load_section_in_dir("Test Config58", "server", dir.path());
+		assert!(if let Ok(c) = config {
+			c == TestServerConfig {
+				host: "h".into(),
+				port: 1,
+			}
+		} else {
+			false
+		});
+		assert!(matches!(
+			TestServerConfig::load_section_in_dir("Test Config58", "database", dir.path()),
+			Err(ConfigError::MissingSection { .. })
+		));
+	}
+
+	#[test]
+	fn load_raw_keeps_unknown_keys() {
+		let dir = TestDir::new("test-config59");
+		std::fs::create_dir(dir.path().join("test-config59")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config59/config.toml"),
+			b"a=\"a\"\nb=\"b\"\n[plugin]\nlevel=3\n",
+		)
+		.unwrap();
+		let value = TestConfig::load_raw_in_dir("Test Config59", dir.path()).unwrap();
+		assert_eq!(value["a"].as_str(), Some("a"));
+		assert_eq!(value["plugin"]["level"].as_integer(), Some(3));
+	}
+
+	#[test]
+	fn human_units() {
+		let config = TestUnitsConfig::load_from_str("timeout = \"1h 30m\"\nmax_size = \"1.5GB\"\n");
+		assert!(if let Ok(c) = config {
+			c == TestUnitsConfig {
+				timeout: std::time::Duration::from_secs(5400).into(),
+				max_size: 1_500_000_000.into(),
+			}
+		} else {
+			false
+		});
+		assert_eq!(
+			configr::HumanDuration(std::time::Duration::from_millis(1500)).to_string(),
+			"1s500ms"
+		);
+		assert_eq!(configr::ByteSize(10 << 20).to_string(), "10MiB");
+		assert!("10 parsecs".parse::<configr::ByteSize>().is_err());
+		assert!(TestUnitsConfig::template_string()
+			.unwrap()
+			.contains("# timeout (configr::HumanDuration, required, e.g. \"30s\")\n"));
+	}
+
+	#[test]
+	fn deprecated_keys_warned() {
+		let dir = TestDir::new("test-config60");
+		std::fs::create_dir(dir.path().join("test-config60")).unwrap();
+		std::fs::write(
+			dir.path().join("test-config60/config.toml"),
+			b"server=\"old\"\nhost=\"h\"\n",
+		)
+		.unwrap();
+		let loaded = TestDeprecatedConfig::load_with_warnings_in_dir("Test Config60", dir.path());
+		assert!(if let Ok((c, warnings)) = loaded {
+			c.server.as_deref() == Some("old")
+				&& warnings == vec!["`server` is deprecated: use host instead".to_string()]
+		} else {
+			false
+		});
+	}
+
+	#[test]
+	fn conflicting_paths_explained() {
+		let dir = TestDir::new("test-config61");
+		std::fs::write(dir.path().join("test-config61"), b"").unwrap();
+		assert!(matches!(
+			TestDefaultConfig::load_in_dir("Test Config61", dir.path()),
+			Err(ConfigError::NotADirectory { .. })
+		));
+		std::fs::remove_file(dir.path().join("test-config61")).unwrap();
+
+		std::fs::create_dir_all(dir.path().join("test-config61/config.toml")).unwrap();
+		assert!(matches!(
+			TestDefaultConfig::load_in_dir("Test Config61", dir.path()),
+			Err(ConfigError::NotAFile { .. })
+		));
+	}
+
+	#[test]
+	fn load_report_only_created_once() {
+		let dir = TestDir::new("test-config53");
+		assert!(matches!(
+			TestDefaultConfig::load_report_in_dir("Test Config53", dir.path()),
+			Ok((_, true))
+		));
+		assert!(matches!(
+			TestDefaultConfig::load_report_in_dir("Test Config53", dir.path()),
+			Ok((_, false))
+		));
+	}
+
+	#[test]
+	fn init_writes_template_once() {
+		let dir = TestDir::new("test-config52");
+		assert!(!TestConfig::exists_in_dir("Test Config52", dir.path()).unwrap());
+		let path = TestConfig::init_in_dir("Test Config52", dir.path()).unwrap();
+		assert!(TestConfig::exists_in_dir("Test Config52", dir.path()).unwrap());
+		assert_eq!(path, dir.path().join("test-config52/config.toml"));
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), TEMPLATE);
+		assert!(matches!(
+			TestConfig::init_in_dir("Test Config52", dir.path()),
+			Err(ConfigError::AlreadyExists { .. })
+		));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn xdg_config_dirs_searched() {
+		let system_dir = std::path::PathBuf::from(configr::SYSTEM_CONFIG_DIR);
+		let dirs = configr::system_config_dirs_from(Some("/opt/xdg-a:relative:/opt/xdg-b".into()), false);
+		assert_eq!(dirs, [
+			std::path::PathBuf::from("/opt/xdg-a"),
+			std::path::PathBuf::from("/opt/xdg-b"),
+			system_dir.clone(),
+		]);
+		assert_eq!(configr::system_config_dirs_from(None, false), [
+			std::path::PathBuf::from("/etc/xdg"),
+			system_dir,
+		]);
+	}
+
+	#[test]
+	fn type_level_file_name() {
+		let dir = TestDir::new("test-config49");
+		let config = TestFileNameConfig {
+			a: "settings".to_string(),
+		};
+		config.save_in_dir("Test Config49", dir.path()).unwrap();
+		assert!(dir.path().join("test-config49/settings.toml").exists());
+		assert!(!dir.path().join("test-config49/config.toml").exists());
+		assert!(
+			if let Ok(c) = TestFileNameConfig::load_in_dir("Test Config49", dir.path()) {
+				c == config
+			} else {
+				false
+			}
+		);
+	}
+
+	#[test]
+	fn type_level_app_name() {
+		assert_eq!(TestAppConfig::APP_NAME, "Test Config50");
+		assert_eq!(
+			TestAppConfig::config_path(TestAppConfig::APP_NAME, true).unwrap(),
+			TestAppConfig::search_paths("Test Config50", true).unwrap()[0]
+		);
+	}
+
+	#[test]
+	fn load_default_uses_app_name() {
+		let dir = TestDir::new("test-config50");
+		assert_eq!(
+			TestAppConfig::load_default_in_dir(dir.path()).unwrap(),
+			TestAppConfig::default()
+		);
+		let path = dir.path().join("test-config50/config.toml");
+		assert!(path.exists());
+
+		std::fs::write(&path, b"a=\"saved\"\n").unwrap();
+		assert_eq!(
+			TestAppConfig::load_default_in_dir(dir.path()).unwrap(),
+			TestAppConfig {
+				a: "saved".to_string()
+			}
+		);
 	}
 }
@@ -0,0 +1,262 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::{
+	app_dir_name,
+	checked_app_dir_name,
+	create_config_file_in,
+	existing_app_config_dir,
+	in_config_dirs,
+	read_config,
+	read_config_with_env,
+	Config,
+	Result,
+};
+
+/// Loads a config with options beyond the ones taken by
+/// [`Config::load`], see [`Config::loader`]
+///
+/// ```no_run
+/// use configr::{Config, Configr};
+/// #[derive(Configr, Default, serde::Serialize, serde::Deserialize)]
+/// #[configr(default)]
+/// pub struct BotConfig {
+///     client_id: String,
+/// }
+///
+/// let config = BotConfig::loader()
+///     .app_name("bot-app")
+///     .file_name("bot.toml")
+///     .env_prefix("BOT")
+///     .load()
+///     .unwrap();
+/// ```
+pub struct ConfigLoader<C> {
+	app_name: String,
+	name_normalizer: Option<fn(&str) -> String>,
+	force_user_dir: bool,
+	file_name: Option<String>,
+	create_if_missing: bool,
+	env_prefix: Option<String>,
+	config_dir: Option<PathBuf>,
+	config: PhantomData<fn() -> C>,
+}
+
+// Implemented by hand so a loader can be cloned without the config
+// itself being Clone
+impl<C> Clone for ConfigLoader<C> {
+	fn clone(&self) -> Self {
+		ConfigLoader {
+			app_name: self.app_name.clone(),
+			name_normalizer: self.name_normalizer,
+			force_user_dir: self.force_user_dir,
+			file_name: self.file_name.clone(),
+			create_if_missing: self.create_if_missing,
+			env_prefix: self.env_prefix.clone(),
+			config_dir: self.config_dir.clone(),
+			config: PhantomData,
+		}
+	}
+}
+
+impl<C> ConfigLoader<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	/// A loader with the same behaviour as [`Config::load`], once
+	/// given an app name
+	pub fn new() -> Self {
+		ConfigLoader {
+			app_name: String::new(),
+			name_normalizer: None,
+			force_user_dir: false,
+			file_name: None,
+			create_if_missing: true,
+			env_prefix: None,
+			config_dir: None,
+			config: PhantomData,
+		}
+	}
+
+	/// The name of the app, which is converted to
+	/// lowercase-kebab-case for its directory\
+	/// This has to be set, loading fails with
+	/// [`ConfigError::InvalidAppName`][crate::ConfigError::
+	/// InvalidAppName] otherwise
+	pub fn app_name(
+		mut self,
+		app_name: impl Into<String>,
+	) -> Self {
+		self.app_name = app_name.into();
+		self
+	}
+
+	/// Whether the app name is converted to lowercase-kebab-case for
+	/// its directory, when `false` the name is used verbatim, such as
+	/// for an existing `MyApp` directory\
+	/// Defaults to `true`, this replaces any
+	/// [`with_name_normalizer`][Self::with_name_normalizer]
+	pub fn normalize_app_name(
+		mut self,
+		normalize_app_name: bool,
+	) -> Self {
+		self.name_normalizer = if normalize_app_name {
+			None
+		} else {
+			Some(|name| name.to_string())
+		};
+		self
+	}
+
+	/// Convert the app name into the name of its directory with
+	/// `normalizer` instead of to lowercase-kebab-case, such as to
+	/// snake_case with `|name| name.replace(' ', "_")`\
+	/// The resulting name is still rejected if it would escape the
+	/// config directory.
+	pub fn with_name_normalizer(
+		mut self,
+		normalizer: fn(&str) -> String,
+	) -> Self {
+		self.name_normalizer = Some(normalizer);
+		self
+	}
+
+	/// Always use the user configuration directory, instead of
+	/// preferring the system one, see [`Config::load`]\
+	/// Defaults to `false`
+	pub fn force_user_dir(
+		mut self,
+		force_user_dir: bool,
+	) -> Self {
+		self.force_user_dir = force_user_dir;
+		self
+	}
+
+	/// The name of the config file in the app directory\
+	/// Defaults to the config's [`file_name`][Config::file_name], the
+	/// file is always parsed as its [`FORMAT`][Config::FORMAT]
+	/// regardless of the extension
+	pub fn file_name(
+		mut self,
+		file_name: impl Into<String>,
+	) -> Self {
+		self.file_name = Some(file_name.into());
+		self
+	}
+
+	/// Whether a missing config file is created from the template\
+	/// Defaults to `true`, when `false` nothing is written and a
+	/// missing file fails with
+	/// [`ConfigError::NotFound`][crate::ConfigError::NotFound], like
+	/// [`Config::load_no_create`]
+	pub fn create_if_missing(
+		mut self,
+		create_if_missing: bool,
+	) -> Self {
+		self.create_if_missing = create_if_missing;
+		self
+	}
+
+	/// Override the values of the config file with environment
+	/// variables named `{PREFIX}_{KEY}`, the same way as
+	/// [`Config::load_with_env`]\
+	/// Defaults to not reading the environment
+	pub fn env_prefix(
+		mut self,
+		prefix: impl Into<String>,
+	) -> Self {
+		self.env_prefix = Some(prefix.into());
+		self
+	}
+
+	/// Use `config_dir` instead of the OS specific configuration
+	/// directories, like [`Config::load_in_dir`]
+	pub fn config_dir(
+		mut self,
+		config_dir: impl Into<PathBuf>,
+	) -> Self {
+		self.config_dir = Some(config_dir.into());
+		self
+	}
+
+	/// Load the config with the chosen options
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`Config::load`], or [`Config::load_no_create`] if
+	/// [`create_if_missing`][Self::create_if_missing] is unset
+	pub fn load(&self) -> Result<C> {
+		let file_name = self.file_name.clone().unwrap_or_else(|| C::file_name());
+		match &self.config_dir {
+			Some(dir) => self.load_in_dir(dir, &file_name),
+			None if self.create_if_missing =>
+				in_config_dirs(self.force_user_dir, C::LOCAL_APP_DATA, C::CURRENT_DIR, |dir| {
+					self.load_in_dir(dir, &file_name)
+				}),
+			None => {
+				let dir = existing_app_config_dir(
+					&self.app_dir()?,
+					&file_name,
+					self.force_user_dir,
+					C::LOCAL_APP_DATA,
+					C::CURRENT_DIR,
+				)?;
+				self.load_in_dir(&dir, &file_name)
+			},
+		}
+	}
+
+	fn load_in_dir(
+		&self,
+		config_dir: &Path,
+		file_name: &str,
+	) -> Result<C> {
+		let app_dir = config_dir.join(self.app_dir()?);
+		let config_location = if self.create_if_missing {
+			create_config_file_in::<C>(app_dir, file_name)?
+		} else {
+			app_dir.join(file_name)
+		};
+		match &self.env_prefix {
+			Some(prefix) => read_config_with_env(&config_location, Some(prefix)),
+			None => read_config(C::FORMAT, &config_location),
+		}
+	}
+
+	/// The name of the app directory, see
+	/// [`with_name_normalizer`][Self::with_name_normalizer]
+	fn app_dir(&self) -> Result<String> {
+		match self.name_normalizer {
+			Some(normalizer) => checked_app_dir_name(&self.app_name, normalizer(&self.app_name)),
+			None => app_dir_name(&self.app_name),
+		}
+	}
+}
+
+impl<C> Default for ConfigLoader<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	fn default() -> Self { ConfigLoader::new() }
+}
+
+// Function pointers taking references don't implement `Debug`
+impl<C> std::fmt::Debug for ConfigLoader<C> {
+	fn fmt(
+		&self,
+		f: &mut std::fmt::Formatter<'_>,
+	) -> std::fmt::Result {
+		f.debug_struct("ConfigLoader")
+			.field("app_name", &self.app_name)
+			.field(
+				"name_normalizer",
+				&self.name_normalizer.map(|_| "fn(&str) -> String"),
+			)
+			.field("force_user_dir", &self.force_user_dir)
+			.field("file_name", &self.file_name)
+			.field("create_if_missing", &self.create_if_missing)
+			.field("env_prefix", &self.env_prefix)
+			.field("config_dir", &self.config_dir)
+			.finish()
+	}
+}
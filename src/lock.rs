@@ -0,0 +1,30 @@
+//! Advisory locks on config files through `fs2`, which do nothing
+//! unless the `lock` feature is enabled
+//!
+//! The locks are released when the file is closed, and being advisory
+//! they only keep out other processes that lock the file as well.
+
+use std::fs::File;
+use std::io;
+
+/// Wait for a shared lock on `file`, which other readers can hold at
+/// the same time
+pub(crate) fn shared(file: &File) -> io::Result<()> {
+	#[cfg(feature = "lock")]
+	fs2::FileExt::lock_shared(file)?;
+	#[cfg(not(feature = "lock"))]
+	let _ = file;
+
+	Ok(())
+}
+
+/// Wait for an exclusive lock on `file`, keeping out both readers and
+/// other writers
+pub(crate) fn exclusive(file: &File) -> io::Result<()> {
+	#[cfg(feature = "lock")]
+	fs2::FileExt::lock_exclusive(file)?;
+	#[cfg(not(feature = "lock"))]
+	let _ = file;
+
+	Ok(())
+}
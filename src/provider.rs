@@ -0,0 +1,72 @@
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use figment::value::{Dict, Map};
+use figment::{Metadata, Profile, Source};
+
+use crate::{migrated, parse_value, read_file, Config, ConfigError};
+
+/// A [`figment::Provider`] reading a config file found the way
+/// configr finds it, see [`Config::provider`]
+///
+/// A missing file provides no values, so that other sources can still
+/// fill the config in.
+///
+/// ```no_run
+/// use configr::{Config, Configr};
+/// use figment::providers::{Env, Serialized};
+/// use figment::Figment;
+/// #[derive(Configr, Default, serde::Serialize, serde::Deserialize)]
+/// #[configr(default)]
+/// pub struct BotConfig {
+///     client_id: String,
+/// }
+///
+/// let config: BotConfig = Figment::from(Serialized::defaults(BotConfig::default()))
+///     .merge(BotConfig::provider("bot-app", false).unwrap())
+///     .merge(Env::prefixed("BOT_"))
+///     .extract()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigProvider<C> {
+	path: PathBuf,
+	config: PhantomData<fn() -> C>,
+}
+
+impl<C> ConfigProvider<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	/// Provide the config file at `path`, which is parsed as the
+	/// config's [`FORMAT`][Config::FORMAT]
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		ConfigProvider {
+			path: path.into(),
+			config: PhantomData,
+		}
+	}
+
+	/// The path of the config file being provided
+	pub fn path(&self) -> &Path { &self.path }
+}
+
+impl<C> figment::Provider for ConfigProvider<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	fn metadata(&self) -> Metadata { Metadata::named("configr file").source(Source::File(self.path.clone())) }
+
+	fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+		let content = match read_file(&self.path) {
+			Ok(content) => content,
+			Err(ConfigError::NotFound { .. }) => return Ok(Map::new()),
+			Err(error) => return Err(error.to_string().into()),
+		};
+		let value = parse_value::<C>(C::FORMAT, &content, &self.path)
+			.and_then(|value| migrated::<C>(value, &self.path))
+			.map_err(|e| e.to_string())?;
+
+		figment::providers::Serialized::defaults(value).data()
+	}
+}
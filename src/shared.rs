@@ -0,0 +1,99 @@
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+use crate::{Config, Result};
+
+/// A loaded config shared between threads, which can be swapped for a
+/// newly loaded one while it is in use
+///
+/// Cloning the handle shares the same config. Readers hold the read
+/// lock for as long as they keep the guard from [`get`][Self::get],
+/// and a reload only takes the write lock once the new config has
+/// been loaded, so readers see either the old or the new config but
+/// never a mix of the two.
+///
+/// ```no_run
+/// use configr::{Config, Configr, SharedConfig};
+/// #[derive(Configr, Default, serde::Serialize, serde::Deserialize)]
+/// #[configr(default)]
+/// pub struct BotConfig {
+///     channel: String,
+/// }
+///
+/// let shared = SharedConfig::new(BotConfig::load("bot-app", false).unwrap());
+/// let handle = shared.clone();
+/// std::thread::spawn(move || println!("{}", handle.get().channel));
+/// shared.reload("bot-app", false).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SharedConfig<C> {
+	inner: Arc<RwLock<C>>,
+}
+
+impl<C> SharedConfig<C>
+where
+	C: serde::de::DeserializeOwned + Config<C>,
+{
+	/// Share `config`
+	pub fn new(config: C) -> Self {
+		SharedConfig {
+			inner: Arc::new(RwLock::new(config)),
+		}
+	}
+
+	/// Read the current config, which isn't replaced until the guard
+	/// is dropped
+	pub fn get(&self) -> RwLockReadGuard<'_, C> {
+		// The lock is only written by swapping in a whole value, so a
+		// poisoned lock still holds a complete config
+		self.inner.read().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Replace the config with `config`, such as one passed to the
+	/// callback of [`Config::watch`]
+	pub fn set(
+		&self,
+		config: C,
+	) {
+		*self.inner.write().unwrap_or_else(PoisonError::into_inner) = config;
+	}
+
+	/// Load the config again with [`Config::load`] and replace the
+	/// shared one with it
+	///
+	/// # Failures
+	/// This has the same failure possibilities as [`Config::load`],
+	/// in which case the current config is kept
+	pub fn reload(
+		&self,
+		app_name: &str,
+		force_user_dir: bool,
+	) -> Result<()> {
+		self.set(C::load(app_name, force_user_dir)?);
+		Ok(())
+	}
+
+	/// Load the config again with [`Config::load_in_dir`] and replace
+	/// the shared one with it
+	///
+	/// # Failures
+	/// This has the same failure possibilities as
+	/// [`Config::load_in_dir`], in which case the current config is
+	/// kept
+	pub fn reload_in_dir(
+		&self,
+		app_name: &str,
+		config_dir: &Path,
+	) -> Result<()> {
+		self.set(C::load_in_dir(app_name, config_dir)?);
+		Ok(())
+	}
+}
+
+impl<C> Clone for SharedConfig<C> {
+	fn clone(&self) -> Self {
+		SharedConfig {
+			inner: Arc::clone(&self.inner),
+		}
+	}
+}
@@ -0,0 +1,1047 @@
+use crate::edit::line_ending;
+use crate::Format;
+
+/// A key in a generated template, as described by the derive macros
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateField {
+	pub(crate) key: String,
+	pub(crate) fields: Option<Vec<TemplateField>>,
+	pub(crate) doc: Vec<String>,
+	pub(crate) default: Option<String>,
+	pub(crate) optional: bool,
+	pub(crate) skipped: bool,
+	pub(crate) type_name: Option<String>,
+	pub(crate) env: Option<String>,
+	pub(crate) map: bool,
+	pub(crate) array: bool,
+	pub(crate) example: Option<String>,
+	pub(crate) deprecated: Option<String>,
+	pub(crate) secret: bool,
+}
+
+impl TemplateField {
+	/// A key which is written without a value, or a table with its
+	/// own keys if `fields` is set
+	pub fn new(
+		key: impl Into<String>,
+		fields: Option<Vec<TemplateField>>,
+	) -> Self {
+		TemplateField {
+			key: key.into(),
+			fields,
+			doc: Vec::new(),
+			default: None,
+			optional: false,
+			skipped: false,
+			type_name: None,
+			env: None,
+			map: false,
+			array: false,
+			example: None,
+			deprecated: None,
+			secret: false,
+		}
+	}
+
+	/// A key which is written without a value
+	pub fn value(key: impl Into<String>) -> Self { TemplateField::new(key, None) }
+
+	/// A table containing `fields`
+	pub fn table(
+		key: impl Into<String>,
+		fields: Vec<TemplateField>,
+	) -> Self {
+		TemplateField::new(key, Some(fields))
+	}
+
+	/// A list such as a `Vec`, which is an array of tables with
+	/// `fields` as the keys of each table if it is set, and otherwise
+	/// written as an empty array
+	pub fn list(
+		key: impl Into<String>,
+		fields: Option<Vec<TemplateField>>,
+	) -> Self {
+		match fields {
+			Some(fields) => TemplateField {
+				array: true,
+				..TemplateField::table(key, fields)
+			},
+			None => TemplateField::value(key).with_default("[]"),
+		}
+	}
+
+	/// Document the key with `doc`, which is written as one comment
+	/// line per entry above it
+	pub fn with_doc(
+		mut self,
+		doc: &[&str],
+	) -> Self {
+		self.doc = doc.iter().map(|line| line.to_string()).collect();
+		self
+	}
+
+	/// Show `value` as an example in a comment above the key, without
+	/// it being the value
+	pub fn with_example(
+		mut self,
+		value: &str,
+	) -> Self {
+		self.example = Some(value.to_string());
+		self
+	}
+
+	/// Write `value` verbatim after the key instead of leaving it
+	/// empty
+	pub fn with_default(
+		mut self,
+		value: &str,
+	) -> Self {
+		self.default = Some(value.to_string());
+		self
+	}
+
+	/// Mark the key as optional, which comments it out in TOML
+	/// templates so leaving it unset still parses
+	pub fn optional(mut self) -> Self {
+		self.optional = true;
+		self
+	}
+
+	/// Mark the key as a map with arbitrary keys, such as a
+	/// `HashMap`, which is written as an empty table whose keys are
+	/// all known
+	pub fn map(mut self) -> Self {
+		self.map = true;
+		self
+	}
+
+	/// Mark the key as skipped, which leaves it out of templates
+	/// while it is still a known key of the config
+	pub fn skipped(mut self) -> Self {
+		self.skipped = true;
+		self
+	}
+
+	/// Name the Rust type of the key, which TOML templates hint at in
+	/// a comment above keys that have to be filled in
+	pub fn with_type(
+		mut self,
+		type_name: &str,
+	) -> Self {
+		self.type_name = Some(type_name.to_string());
+		self
+	}
+
+	/// Override the key with the environment variable `var` when
+	/// loading with environment overrides, such as
+	/// [`load_with_field_env`][crate::Config::load_with_field_env]
+	pub fn with_env(
+		mut self,
+		var: &str,
+	) -> Self {
+		self.env = Some(var.to_string());
+		self
+	}
+
+	/// Mark the key as deprecated with `note`, such as `use new_key
+	/// instead`, which
+	/// [`load_with_warnings`][crate::Config::load_with_warnings]
+	/// warns about when the key is present
+	pub fn deprecated(
+		mut self,
+		note: &str,
+	) -> Self {
+		self.deprecated = Some(note.to_string());
+		self
+	}
+
+	/// Mark the key as secret, such as a password or token, which
+	/// [`dump`][crate::Config::dump] leaves out when redacting
+	pub fn secret(mut self) -> Self {
+		self.secret = true;
+		self
+	}
+
+	/// The key the field is stored under
+	pub fn key(&self) -> &str { &self.key }
+
+	/// The keys of the table, if the field is one
+	pub fn fields(&self) -> Option<&[TemplateField]> { self.fields.as_deref() }
+
+	/// The comment lines written above the key
+	pub fn doc(&self) -> &[String] { &self.doc }
+
+	/// The value written after the key, if it has one
+	pub fn default(&self) -> Option<&str> { self.default.as_deref() }
+
+	/// Whether the key is optional
+	pub fn is_optional(&self) -> bool { self.optional }
+
+	/// Whether the key is left out of templates
+	pub fn is_skipped(&self) -> bool { self.skipped }
+
+	/// Whether the key is a map with arbitrary keys
+	pub fn is_map(&self) -> bool { self.map }
+
+	/// Whether the key is an array of tables, see
+	/// [`list`][Self::list]
+	pub fn is_array(&self) -> bool { self.array }
+
+	/// The Rust type of the key, if it was named
+	pub fn type_name(&self) -> Option<&str> { self.type_name.as_deref() }
+
+	/// The environment variable overriding the key, if it has one
+	pub fn env(&self) -> Option<&str> { self.env.as_deref() }
+
+	/// The example value shown above the key, if it has one
+	pub fn example(&self) -> Option<&str> { self.example.as_deref() }
+
+	/// The note explaining why the key is deprecated, if it is
+	pub fn deprecation(&self) -> Option<&str> { self.deprecated.as_deref() }
+
+	/// Whether the key is secret, see [`secret`][Self::secret]
+	pub fn is_secret(&self) -> bool { self.secret }
+
+	/// The comment lines written above the key, which are its doc
+	/// followed by its example
+	fn comments(&self) -> Vec<String> {
+		let mut comments = self.doc.clone();
+		if let Some(example) = &self.example {
+			comments.push(format!("example: {}", example));
+		}
+		comments
+	}
+
+	/// The comment hinting at what to fill in for a required key
+	/// without a value, like `port (u16, required, e.g. 0)`
+	fn type_hint(&self) -> Option<String> {
+		if self.optional || self.default.is_some() {
+			return None;
+		}
+		let type_name = self.type_name.as_deref()?;
+		if self.map || self.array {
+			return Some(format!("{} ({})", self.key, type_name));
+		}
+		// Types may be written with their path, like `configr::ByteSize`
+		let example = match type_name.rsplit("::").next().unwrap_or(type_name) {
+			"bool" => ", true or false",
+			"i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+			| "usize" | "f32" | "f64" => ", e.g. 0",
+			"String" | "&str" | "&'static str" => ", e.g. \"\"",
+			"HumanDuration" => ", e.g. \"30s\"",
+			"ByteSize" => ", e.g. \"10MB\"",
+			_ => "",
+		};
+		Some(format!("{} ({}, required{})", self.key, type_name, example))
+	}
+}
+
+/// Render `fields` as a template in `format`, with `doc` as a header
+/// comment
+pub(crate) fn render(
+	format: Format,
+	doc: &[&str],
+	fields: &[TemplateField],
+) -> String {
+	let fields = &without_skipped(fields);
+	let mut out = String::new();
+	match format {
+		Format::Toml => {
+			push_header(&mut out, doc);
+			render_toml(&mut out, fields, &mut Vec::new());
+		},
+		#[cfg(feature = "json")]
+		Format::Json => {
+			render_json(&mut out, fields, 0);
+			out.push('\n');
+		},
+		#[cfg(feature = "yaml")]
+		Format::Yaml => {
+			push_header(&mut out, doc);
+			render_yaml(&mut out, fields, 0);
+		},
+		#[cfg(feature = "ron")]
+		Format::Ron => {
+			render_ron(&mut out, fields, 0);
+			out.push('\n');
+		},
+	}
+	out
+}
+
+/// Copy `fields` leaving out the skipped ones, along with those in
+/// their tables
+fn without_skipped(fields: &[TemplateField]) -> Vec<TemplateField> {
+	fields
+		.iter()
+		.filter(|f| !f.skipped)
+		.map(|f| TemplateField {
+			fields: f.fields.as_deref().map(without_skipped),
+			..f.clone()
+		})
+		.collect()
+}
+
+/// The dotted paths of the keys in `value` that aren't among
+/// `fields`, looking into the tables of fields which are tables
+/// themselves
+pub(crate) fn unknown_keys(
+	value: &toml::Value,
+	fields: &[TemplateField],
+) -> Vec<String> {
+	let mut unknown = Vec::new();
+	push_unknown_keys(&mut unknown, value, fields, "");
+	unknown
+}
+
+/// A warning for each key in `value` whose field is deprecated,
+/// looking into the tables of fields which are tables themselves
+pub(crate) fn deprecated_keys(
+	value: &toml::Value,
+	fields: &[TemplateField],
+) -> Vec<String> {
+	let mut warnings = Vec::new();
+	push_deprecated_keys(&mut warnings, value, fields, "");
+	warnings
+}
+
+/// Replace the values of the secret fields in `value` with `"***"`,
+/// looking into the tables of fields which are tables themselves
+pub(crate) fn redact(
+	value: &mut toml::Value,
+	fields: &[TemplateField],
+) {
+	let table = match value.as_table_mut() {
+		Some(table) => table,
+		None => return,
+	};
+	for field in fields {
+		let value = match table.get_mut(&field.key) {
+			Some(value) => value,
+			None => continue,
+		};
+		if field.secret {
+			*value = toml::Value::String("***".to_string());
+			continue;
+		}
+		match (&field.fields, value) {
+			(Some(table_fields), toml::Value::Array(values)) if field.array =>
+				for value in values {
+					redact(value, table_fields);
+				},
+			(Some(table_fields), value) if !field.map => redact(value, table_fields),
+			_ => {},
+		}
+	}
+}
+
+/// Replace the values of the secret fields in the raw `content` of a
+/// config file with `"***"`, returning the redacted content along
+/// with the values that were replaced\
+/// As the content may not parse at all this works line by line, so
+/// any `key = value` or `key: value` line whose key is the name of a
+/// secret field is redacted, whichever table it is in. A TOML
+/// multi-line string is redacted up to its closing quotes, leaving
+/// the lines it spans empty so the line numbers of errors still
+/// match.
+pub(crate) fn redact_content(
+	content: &str,
+	fields: &[TemplateField],
+) -> (String, Vec<String>) {
+	let mut keys = Vec::new();
+	push_secret_keys(&mut keys, fields);
+	if keys.is_empty() {
+		return (content.to_string(), Vec::new());
+	}
+	let mut redacted = String::with_capacity(content.len());
+	let mut values = Vec::new();
+	let mut lines = content.split_inclusive('\n');
+	while let Some(line) = lines.next() {
+		let (start, end) = match secret_value(line, &keys) {
+			Some(range) => range,
+			None => {
+				redacted.push_str(line);
+				continue;
+			},
+		};
+		redacted.push_str(&line[..start]);
+		redacted.push_str("\"***\"");
+		let value = &line[start..end];
+		let delimiter = ["\"\"\"", "'''"]
+			.iter()
+			.find(|d| value.starts_with(**d) && !value[3..].contains(**d));
+		let value = match delimiter {
+			Some(delimiter) => {
+				redacted.push_str(line_break(line));
+				let mut value = value[3..].to_string();
+				for line in &mut lines {
+					match line.find(delimiter) {
+						Some(i) => {
+							value.push_str(&line[..i]);
+							redacted.push_str(&line[i + 3..]);
+							break;
+						},
+						None => {
+							value.push_str(line);
+							redacted.push_str(line_break(line));
+						},
+					}
+				}
+				value.trim_start_matches(|c| c == '\r' || c == '\n').to_string()
+			},
+			None => {
+				redacted.push_str(&line[end..]);
+				value.trim_matches(|c| c == '"' || c == '\'').to_string()
+			},
+		};
+		if !value.is_empty() {
+			values.push(value);
+		}
+	}
+
+	(redacted, values)
+}
+
+/// The line break `line` ends with, if any
+fn line_break(line: &str) -> &str { &line[line.trim_end_matches(|c| c == '\r' || c == '\n').len()..] }
+
+/// Replace the `secrets` in an error `message` where they appear as
+/// the unexpected value of a deserialization error, like `string
+/// "hunter2"` or ``integer `1234` ``\
+/// Only those spots are replaced, so a short secret doesn't mangle
+/// line numbers or key names elsewhere in the message.
+pub(crate) fn redact_message(
+	message: &str,
+	secrets: &[String],
+) -> String {
+	let mut redacted = message.to_string();
+	for secret in secrets {
+		redacted = redacted.replace(&format!("string {:?}", secret), "string \"***\"");
+		for kind in &["integer", "floating point", "boolean", "character"] {
+			redacted = redacted.replace(&format!("{} `{}`", kind, secret), &format!("{} `***`", kind));
+		}
+	}
+
+	redacted
+}
+
+fn push_secret_keys<'a>(
+	keys: &mut Vec<&'a str>,
+	fields: &'a [TemplateField],
+) {
+	for field in fields {
+		if field.secret {
+			keys.push(&field.key);
+		}
+		if let Some(fields) = &field.fields {
+			push_secret_keys(keys, fields);
+		}
+	}
+}
+
+/// The byte range of the value in `line` if its key is one of `keys`,
+/// leaving out the whitespace around it and a trailing comma
+fn secret_value(
+	line: &str,
+	keys: &[&str],
+) -> Option<(usize, usize)> {
+	let separator = line.find(|c: char| c == '=' || c == ':')?;
+	let key = line[..separator].trim().trim_matches(|c| c == '"' || c == '\'');
+	if !keys.contains(&key.rsplit('.').next().unwrap_or(key)) {
+		return None;
+	}
+	let value = &line[separator + 1..];
+	let start = separator + 1 + value.len() - value.trim_start().len();
+	let value = line[start..].trim_end();
+	let end = start + value.strip_suffix(',').unwrap_or(value).trim_end().len();
+
+	Some((start, end)).filter(|(start, end)| start < end)
+}
+
+fn push_deprecated_keys(
+	warnings: &mut Vec<String>,
+	value: &toml::Value,
+	fields: &[TemplateField],
+	prefix: &str,
+) {
+	let table = match value.as_table() {
+		Some(table) => table,
+		None => return,
+	};
+	for field in fields {
+		let value = match table.get(&field.key) {
+			Some(value) => value,
+			None => continue,
+		};
+		let path = format!("{}{}", prefix, field.key);
+		if let Some(note) = &field.deprecated {
+			warnings.push(format!("`{}` is deprecated: {}", path, note));
+		}
+		match (&field.fields, field.map) {
+			(Some(table_fields), false) if field.array =>
+				for (i, value) in value.as_array().into_iter().flatten().enumerate() {
+					push_deprecated_keys(warnings, value, table_fields, &format!("{}.{}.", path, i));
+				},
+			(Some(table_fields), false) =>
+				push_deprecated_keys(warnings, value, table_fields, &format!("{}.", path)),
+			_ => {},
+		}
+	}
+}
+
+fn push_unknown_keys(
+	unknown: &mut Vec<String>,
+	value: &toml::Value,
+	fields: &[TemplateField],
+	prefix: &str,
+) {
+	let table = match value.as_table() {
+		Some(table) => table,
+		None => return,
+	};
+	for (key, value) in table {
+		let path = format!("{}{}", prefix, key);
+		match fields.iter().find(|f| &f.key == key) {
+			Some(TemplateField { map: true, .. }) => {},
+			Some(TemplateField {
+				fields: Some(table_fields),
+				array: true,
+				..
+			}) =>
+				for (i, value) in value.as_array().into_iter().flatten().enumerate() {
+					push_unknown_keys(unknown, value, table_fields, &format!("{}.{}.", path, i));
+				},
+			Some(TemplateField {
+				fields: Some(table_fields),
+				..
+			}) => push_unknown_keys(unknown, value, table_fields, &format!("{}.", path)),
+			Some(_) => {},
+			None => unknown.push(path),
+		}
+	}
+}
+
+/// Add the keys of `fields` missing from the TOML document `content`,
+/// which parses to `value`, returning the new document along with the
+/// dotted paths of the added keys
+///
+/// Missing keys of a table are inserted at the end of its section and
+/// missing tables at the end of the document, rendered the way
+/// templates are with their values taken from `defaults`. Keys
+/// without a value are commented out so the document still parses,
+/// and keys of tables without a `[table]` header of their own, such
+/// as inline tables, are left out.
+pub(crate) fn sync_toml(
+	content: &str,
+	value: &toml::Value,
+	fields: &[TemplateField],
+	defaults: Option<&toml::Value>,
+) -> (String, Vec<String>) {
+	let mut missing = Missing::default();
+	if let Some(table) = value.as_table() {
+		push_missing(
+			&mut missing,
+			&without_skipped(fields),
+			table,
+			defaults,
+			&mut Vec::new(),
+		);
+	}
+
+	let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+	let headers: Vec<(usize, Vec<String>)> = lines
+		.iter()
+		.enumerate()
+		.filter_map(|(i, line)| header_path(line).map(|path| (i, path)))
+		.collect();
+	let mut inserts = Vec::new();
+	let mut added = Vec::new();
+	for (path, rendered, keys) in missing.keys {
+		let start = if path.is_empty() {
+			Some(0)
+		} else {
+			headers.iter().find(|(_, p)| *p == path).map(|(i, _)| i + 1)
+		};
+		let start = match start {
+			Some(start) => start,
+			None => continue,
+		};
+		let mut end = headers
+			.iter()
+			.map(|(i, _)| *i)
+			.find(|i| *i >= start)
+			.unwrap_or_else(|| lines.len());
+		// Comments and blank lines before the next header belong to it
+		while end > start && belongs_to_header(&lines[end - 1]) {
+			end -= 1;
+		}
+		inserts.push((end, rendered));
+		added.extend(keys);
+	}
+	// Inserting from the bottom keeps the positions above valid
+	inserts.sort_by(|a, b| b.0.cmp(&a.0));
+	for (at, rendered) in inserts {
+		let rest = lines.split_off(at);
+		lines.extend(rendered.lines().map(str::to_string));
+		lines.extend(rest);
+	}
+	for (rendered, key) in missing.tables {
+		if lines.last().map_or(false, |l| !l.trim().is_empty()) {
+			lines.push(String::new());
+		}
+		lines.extend(rendered.lines().map(str::to_string));
+		added.push(key);
+	}
+
+	let ending = line_ending(content);
+	let mut out = lines.join(ending);
+	if !out.is_empty() {
+		out.push_str(ending);
+	}
+	(out, added)
+}
+
+/// Whether `line` is a comment or blank, which in front of a table
+/// header are taken to be about that table
+fn belongs_to_header(line: &str) -> bool {
+	let line = line.trim_start();
+	line.is_empty() || line.starts_with('#')
+}
+
+/// The keys [`sync_toml`] adds
+#[derive(Default)]
+struct Missing {
+	/// The path of each table with missing keys, along with the keys
+	/// rendered and their dotted paths
+	keys: Vec<(Vec<String>, String, Vec<String>)>,
+	/// Each rendered missing table, along with its dotted path
+	tables: Vec<(String, String)>,
+}
+
+fn push_missing(
+	missing: &mut Missing,
+	fields: &[TemplateField],
+	table: &toml::value::Table,
+	defaults: Option<&toml::Value>,
+	path: &mut Vec<String>,
+) {
+	let mut keys = Vec::new();
+	let mut rendered = Vec::new();
+	for field in fields {
+		let default = defaults.and_then(|d| d.get(&field.key));
+		match (table.get(&field.key), &field.fields) {
+			(Some(toml::Value::Table(table)), Some(table_fields)) if !field.map && !field.array => {
+				path.push(field.key.clone());
+				push_missing(missing, table_fields, table, default, path);
+				path.pop();
+			},
+			(Some(_), _) => {},
+			(None, Some(_)) => {
+				let mut out = String::new();
+				render_toml(&mut out, &[filled(field, default)], &mut toml_path(path));
+				missing.tables.push((out, dotted(path, &field.key)));
+			},
+			(None, None) => {
+				rendered.push(filled(field, default));
+				keys.push(dotted(path, &field.key));
+			},
+		}
+	}
+	if !rendered.is_empty() {
+		let mut out = String::new();
+		render_toml(&mut out, &rendered, &mut toml_path(path));
+		missing.keys.push((path.clone(), out, keys));
+	}
+}
+
+/// Copy `field` with the value in `default`, or commented out if it
+/// has none, along with the keys of its table
+fn filled(
+	field: &TemplateField,
+	default: Option<&toml::Value>,
+) -> TemplateField {
+	let mut field = field.clone();
+	match &mut field.fields {
+		Some(fields) if !field.map && !field.array =>
+			for f in fields.iter_mut() {
+				*f = filled(f, default.and_then(|d| d.get(&f.key)));
+			},
+		Some(_) => {},
+		None => {
+			if field.default.is_none() {
+				field.default = default.filter(|d| !d.is_table()).map(toml::Value::to_string);
+			}
+			if field.default.is_none() {
+				field.optional = true;
+			}
+		},
+	}
+	field
+}
+
+/// The dotted path of `key` in the table at `path`
+fn dotted(
+	path: &[String],
+	key: &str,
+) -> String {
+	path.iter()
+		.map(String::as_str)
+		.chain(Some(key))
+		.collect::<Vec<_>>()
+		.join(".")
+}
+
+/// `path` with each key quoted if needed, like [`render_toml`] takes
+/// it
+fn toml_path(path: &[String]) -> Vec<String> { path.iter().map(|k| toml_key(k)).collect() }
+
+/// The path of the table a `[table]` or `[[table]]` header line
+/// starts
+fn header_path(line: &str) -> Option<Vec<String>> {
+	let mut rest = line.trim_start().strip_prefix('[')?;
+	rest = rest.strip_prefix('[').unwrap_or(rest);
+	let mut path = Vec::new();
+	loop {
+		let (key, after) = split_toml_key(rest.trim_start());
+		if key.is_empty() {
+			return None;
+		}
+		path.push(key);
+		let after = after.trim_start();
+		match after.strip_prefix('.') {
+			Some(after) => rest = after,
+			None => {
+				// Lines of multiline arrays can start with `[` too
+				let after = after.strip_prefix(']')?.trim_start_matches(']').trim_start();
+				return if after.is_empty() || after.starts_with('#') {
+					Some(path)
+				} else {
+					None
+				};
+			},
+		}
+	}
+}
+
+/// Add comments for the fields' docs to `content`, which is a
+/// template that was serialized by `format` rather than rendered from
+/// `fields`, with `doc` as a header comment\
+/// Only TOML and YAML support comments, content in any other format
+/// is returned as is.
+pub(crate) fn annotate(
+	format: Format,
+	content: &str,
+	doc: &[&str],
+	fields: &[TemplateField],
+) -> String {
+	match format {
+		Format::Toml => annotate_toml(content, doc, fields),
+		#[cfg(feature = "yaml")]
+		Format::Yaml => annotate_yaml(content, doc, fields),
+		#[allow(unreachable_patterns)]
+		_ => content.to_string(),
+	}
+}
+
+/// Write `lines` as comments indented by `indent`
+fn push_comments<S>(
+	out: &mut String,
+	lines: &[S],
+	indent: &str,
+) where
+	S: AsRef<str>,
+{
+	for line in lines {
+		let line = line.as_ref();
+		out.push_str(indent);
+		if line.is_empty() {
+			out.push_str("#\n");
+		} else {
+			out.push_str(&format!("# {}\n", line));
+		}
+	}
+}
+
+/// Write `doc` as comments followed by a blank line, if there is any
+fn push_header(
+	out: &mut String,
+	doc: &[&str],
+) {
+	if !doc.is_empty() {
+		push_comments(out, doc, "");
+		out.push('\n');
+	}
+}
+
+fn render_toml(
+	out: &mut String,
+	fields: &[TemplateField],
+	path: &mut Vec<String>,
+) {
+	// Plain keys have to come before any table, otherwise they would end
+	// up inside the last table.
+	for field in fields.iter().filter(|f| f.fields.is_none()) {
+		push_comments(out, &field.comments(), "");
+		if let Some(hint) = field.type_hint() {
+			push_comments(out, &[hint], "");
+		}
+		let key = toml_key(&field.key);
+		let value = field.default.as_deref().unwrap_or_default();
+		if field.optional {
+			out.push_str(&format!("# {} = {}\n", key, value));
+		} else {
+			out.push_str(&format!("{}={}\n", key, value));
+		}
+	}
+	for field in fields {
+		if let Some(table) = &field.fields {
+			path.push(toml_key(&field.key));
+			if !out.is_empty() && !out.ends_with("\n\n") {
+				out.push('\n');
+			}
+			push_comments(out, &field.comments(), "");
+			// Tables of other configs are described by their own keys
+			if let Some(hint) = field.type_hint().filter(|_| field.map || field.array) {
+				push_comments(out, &[hint], "");
+			}
+			if field.array {
+				// A single element shows the shape of the tables, commented
+				// out as the array may well be empty
+				let mut element = format!("[[{}]]\n", path.join("."));
+				render_toml(&mut element, table, path);
+				for line in element.lines() {
+					if line.is_empty() || line.starts_with('#') {
+						out.push_str(&format!("{}\n", line));
+					} else {
+						out.push_str(&format!("# {}\n", line));
+					}
+				}
+			} else {
+				out.push_str(&format!("[{}]\n", path.join(".")));
+				render_toml(out, table, path);
+			}
+			path.pop();
+		}
+	}
+}
+
+/// Quote `key` unless it is a valid bare TOML key
+fn toml_key(key: &str) -> String {
+	if !key.is_empty()
+		&& key
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+	{
+		key.to_string()
+	} else {
+		toml::Value::String(key.to_string()).to_string()
+	}
+}
+
+fn annotate_toml(
+	content: &str,
+	doc: &[&str],
+	fields: &[TemplateField],
+) -> String {
+	let mut out = String::new();
+	push_header(&mut out, doc);
+	// The fields of the table the current line is in, `None` once inside
+	// a table we know nothing about
+	let mut table = Some(fields);
+	for line in content.lines() {
+		let trimmed = line.trim_start();
+		if trimmed.starts_with('[') {
+			let path = split_toml_path(trimmed.trim_start_matches('[').trim_end().trim_end_matches(']'));
+			let field = find_field(fields, &path);
+			if let Some(field) = field {
+				push_comments(&mut out, &field.comments(), "");
+			}
+			table = field.and_then(TemplateField::fields);
+		} else if let Some(key) = toml_line_key(trimmed) {
+			if let Some(field) = table.and_then(|t| t.iter().find(|f| f.key == key)) {
+				push_comments(&mut out, &field.comments(), "");
+			}
+		}
+		out.push_str(line);
+		out.push('\n');
+	}
+	out
+}
+
+/// Find the field at `path` among `fields` and their tables
+fn find_field<'a>(
+	fields: &'a [TemplateField],
+	path: &[String],
+) -> Option<&'a TemplateField> {
+	let (first, rest) = path.split_first()?;
+	let field = fields.iter().find(|f| &f.key == first)?;
+	if rest.is_empty() {
+		Some(field)
+	} else {
+		find_field(field.fields()?, rest)
+	}
+}
+
+/// Split a dotted TOML key like `a."b.c"` into its parts
+fn split_toml_path(path: &str) -> Vec<String> {
+	let mut parts = Vec::new();
+	let mut rest = path.trim();
+	while !rest.is_empty() {
+		let (key, after) = split_toml_key(rest);
+		parts.push(key);
+		rest = after.trim_start().trim_start_matches('.').trim_start();
+	}
+	parts
+}
+
+/// Split the first key off of `s`, unquoting it if needed
+fn split_toml_key(s: &str) -> (String, &str) {
+	match s.chars().next() {
+		Some(quote @ '"') | Some(quote @ '\'') => {
+			let mut escaped = false;
+			for (i, c) in s.char_indices().skip(1) {
+				if c == quote && !escaped {
+					let key = &s[..=i];
+					let key = toml::from_str::<toml::Value>(&format!("k = {}", key))
+						.ok()
+						.and_then(|v| v.get("k").and_then(|k| k.as_str()).map(str::to_string))
+						.unwrap_or_else(|| key[1..key.len() - 1].to_string());
+					return (key, &s[i + 1..]);
+				}
+				escaped = quote == '"' && c == '\\' && !escaped;
+			}
+			(s.to_string(), "")
+		},
+		_ => {
+			let end = s
+				.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+				.unwrap_or_else(|| s.len());
+			(s[..end].to_string(), &s[end..])
+		},
+	}
+}
+
+/// The key assigned to on a `key = value` line, if it is one
+fn toml_line_key(line: &str) -> Option<String> {
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	let (key, rest) = split_toml_key(line);
+	if rest.trim_start().starts_with('=') {
+		Some(key)
+	} else {
+		None
+	}
+}
+
+#[cfg(feature = "json")]
+fn render_json(
+	out: &mut String,
+	fields: &[TemplateField],
+	depth: usize,
+) {
+	if fields.is_empty() {
+		out.push_str("{}");
+		return;
+	}
+	out.push_str("{\n");
+	for (i, field) in fields.iter().enumerate() {
+		out.push_str(&"  ".repeat(depth + 1));
+		out.push_str(&format!("{}: ", serde_json::Value::String(field.key.clone())));
+		match &field.fields {
+			Some(_) if field.array => out.push_str("[]"),
+			Some(table) => render_json(out, table, depth + 1),
+			None => out.push_str(field.default.as_deref().unwrap_or("null")),
+		}
+		if i + 1 != fields.len() {
+			out.push(',');
+		}
+		out.push('\n');
+	}
+	out.push_str(&"  ".repeat(depth));
+	out.push('}');
+}
+
+#[cfg(feature = "ron")]
+fn render_ron(
+	out: &mut String,
+	fields: &[TemplateField],
+	depth: usize,
+) {
+	if fields.is_empty() {
+		out.push_str("()");
+		return;
+	}
+	out.push_str("(\n");
+	for field in fields {
+		out.push_str(&"    ".repeat(depth + 1));
+		out.push_str(&format!("{}: ", field.key));
+		match &field.fields {
+			Some(_) if field.array => out.push_str("[]"),
+			Some(table) => render_ron(out, table, depth + 1),
+			None => out.push_str(field.default.as_deref().unwrap_or("None")),
+		}
+		out.push_str(",\n");
+	}
+	out.push_str(&"    ".repeat(depth));
+	out.push(')');
+}
+
+#[cfg(feature = "yaml")]
+fn render_yaml(
+	out: &mut String,
+	fields: &[TemplateField],
+	depth: usize,
+) {
+	for field in fields {
+		let indent = "  ".repeat(depth);
+		push_comments(out, &field.comments(), &indent);
+		out.push_str(&indent);
+		out.push_str(&field.key);
+		match &field.fields {
+			Some(_) if field.array => out.push_str(": []\n"),
+			Some(table) if table.is_empty() => out.push_str(": {}\n"),
+			Some(table) => {
+				out.push_str(":\n");
+				render_yaml(out, table, depth + 1);
+			},
+			None => match &field.default {
+				Some(value) => out.push_str(&format!(": {}\n", value)),
+				None => out.push_str(":\n"),
+			},
+		}
+	}
+}
+
+#[cfg(feature = "yaml")]
+fn annotate_yaml(
+	content: &str,
+	doc: &[&str],
+	fields: &[TemplateField],
+) -> String {
+	let mut out = String::new();
+	push_header(&mut out, doc);
+	// The indentation of each mapping key we're inside of, along with the
+	// fields of its table
+	let mut parents: Vec<(usize, Option<&[TemplateField]>)> = Vec::new();
+	for line in content.lines() {
+		let trimmed = line.trim_start();
+		let indent = line.len() - trimmed.len();
+		let is_key = !trimmed.starts_with('-') && !trimmed.starts_with('#') && trimmed.contains(':');
+		if is_key {
+			let key = trimmed[..trimmed.find(':').unwrap_or(0)].trim_matches(|c| c == '"' || c == '\'');
+			while parents.last().map_or(false, |&(i, _)| i >= indent) {
+				parents.pop();
+			}
+			let table = parents.last().map_or(Some(fields), |&(_, t)| t);
+			let field = table.and_then(|t| t.iter().find(|f| f.key == key));
+			if let Some(field) = field {
+				push_comments(&mut out, &field.comments(), &line[..indent]);
+			}
+			parents.push((indent, field.and_then(TemplateField::fields)));
+		}
+		out.push_str(line);
+		out.push('\n');
+	}
+	out
+}
@@ -0,0 +1,15 @@
+//! Logging of load events through `tracing`, which compiles to
+//! nothing unless the `tracing` feature is enabled
+
+/// Emit a `tracing` event at `$level`, such as `debug` or `warn`,
+/// with a message formatted like [`format!`]\
+/// Without the `tracing` feature the arguments are only type checked,
+/// so nothing is formatted or logged.
+macro_rules! event {
+	($level:ident, $($arg:tt)+) => {
+		#[cfg(feature = "tracing")]
+		tracing::$level!($($arg)+);
+		#[cfg(not(feature = "tracing"))]
+		let _ = format_args!($($arg)+);
+	};
+}
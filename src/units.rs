@@ -0,0 +1,315 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Errors from parsing a [`HumanDuration`] or [`ByteSize`]
+#[derive(snafu::Snafu, Debug)]
+#[non_exhaustive]
+pub enum UnitError {
+	/// The string isn't a duration like `30s` or `1h30m`.
+	#[snafu(display("Invalid duration {:?}, expected e.g. \"30s\" or \"1h30m\"", input))]
+	Duration { input: String },
+	/// The string isn't a byte size like `512KiB` or `10MB`.
+	#[snafu(display("Invalid byte size {:?}, expected e.g. \"512KiB\" or \"10MB\"", input))]
+	ByteSize { input: String },
+}
+
+/// A [`Duration`] written in config files as a human friendly string
+/// like `30s`, `250ms` or `1h 30m`
+///
+/// The units are `ns`, `us`, `ms`, `s`, `m` or `min`, `h` and `d`,
+/// and several of them add up. A plain integer is read as seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct HumanDuration(pub Duration);
+
+/// A number of bytes written in config files as a human friendly
+/// string like `10MB` or `512KiB`
+///
+/// `KB`, `MB`, `GB` and `TB` are powers of 1000 while `KiB`, `MiB`,
+/// `GiB` and `TiB` are powers of 1024, the units aren't case
+/// sensitive and may be followed by a fraction like `1.5GB`. A plain
+/// integer is read as bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ByteSize(pub u64);
+
+/// The duration units from largest to smallest, in nanoseconds
+const DURATION_UNITS: &[(&str, u128)] = &[
+	("d", 86_400_000_000_000),
+	("h", 3_600_000_000_000),
+	("m", 60_000_000_000),
+	("s", 1_000_000_000),
+	("ms", 1_000_000),
+	("us", 1_000),
+	("ns", 1),
+];
+
+/// The byte size units from largest to smallest
+const BYTE_UNITS: &[(&str, u64)] = &[
+	("TiB", 1 << 40),
+	("TB", 1_000_000_000_000),
+	("GiB", 1 << 30),
+	("GB", 1_000_000_000),
+	("MiB", 1 << 20),
+	("MB", 1_000_000),
+	("KiB", 1 << 10),
+	("KB", 1_000),
+];
+
+/// Split `input` into numbers each followed by a unit, like
+/// `[("1", "h"), ("30", "m")]` for `1h 30m`
+fn components(input: &str) -> Option<Vec<(&str, &str)>> {
+	let mut components = Vec::new();
+	let mut rest = input.trim();
+	while !rest.is_empty() {
+		let number_end = rest
+			.find(|c: char| !c.is_ascii_digit() && c != '.')
+			.unwrap_or_else(|| rest.len());
+		let (number, after) = rest.split_at(number_end);
+		let after = after.trim_start();
+		let unit_end = after
+			.find(|c: char| !c.is_alphabetic())
+			.unwrap_or_else(|| after.len());
+		let (unit, after) = after.split_at(unit_end);
+		if number.is_empty() {
+			return None;
+		}
+		components.push((number, unit));
+		rest = after.trim_start();
+	}
+	Some(components)
+}
+
+impl FromStr for HumanDuration {
+	type Err = UnitError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let invalid = || UnitError::Duration { input: input.into() };
+		let components = components(input).filter(|c| !c.is_empty()).ok_or_else(invalid)?;
+		let mut nanos = 0u128;
+		for (number, unit) in &components {
+			let unit = match *unit {
+				"" if components.len() == 1 => "s",
+				"min" => "m",
+				unit => unit,
+			};
+			let scale = DURATION_UNITS
+				.iter()
+				.find(|(u, _)| *u == unit)
+				.ok_or_else(invalid)?
+				.1;
+			// Integers are added exactly, only fractions like `1.5h` go
+			// through floats.
+			let component = match number.parse::<u128>() {
+				Ok(n) => n.checked_mul(scale),
+				Err(_) => number
+					.parse::<f64>()
+					.ok()
+					.map(|n| (n * scale as f64).round())
+					.filter(|n| n.is_finite() && *n <= u128::MAX as f64)
+					.map(|n| n as u128),
+			};
+			nanos = component.and_then(|c| nanos.checked_add(c)).ok_or_else(invalid)?;
+		}
+		let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| invalid())?;
+
+		Ok(HumanDuration(Duration::new(secs, (nanos % 1_000_000_000) as u32)))
+	}
+}
+
+impl fmt::Display for HumanDuration {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		let mut nanos = self.0.as_nanos();
+		if nanos == 0 {
+			return f.write_str("0s");
+		}
+		for (unit, scale) in DURATION_UNITS {
+			if nanos >= *scale {
+				write!(f, "{}{}", nanos / scale, unit)?;
+				nanos %= scale;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for ByteSize {
+	type Err = UnitError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let invalid = || UnitError::ByteSize { input: input.into() };
+		let (number, unit) = match components(input).as_deref() {
+			Some(&[component]) => component,
+			_ => return Err(invalid()),
+		};
+		let unit = unit.to_ascii_lowercase();
+		// `KB` and `K` are the same, just as `KiB` and `Ki`
+		let scale = match unit.strip_suffix('b').unwrap_or(&unit) {
+			"" => 1,
+			prefix =>
+				BYTE_UNITS
+					.iter()
+					.find(|(u, _)| u[..u.len() - 1].eq_ignore_ascii_case(prefix))
+					.ok_or_else(invalid)?
+					.1,
+		};
+		let bytes = match number.parse::<u64>() {
+			Ok(n) => n.checked_mul(scale),
+			Err(_) => number
+				.parse::<f64>()
+				.ok()
+				.map(|n| (n * scale as f64).round())
+				.filter(|n| n.is_finite() && *n <= u64::MAX as f64)
+				.map(|n| n as u64),
+		};
+
+		bytes.map(ByteSize).ok_or_else(invalid)
+	}
+}
+
+impl fmt::Display for ByteSize {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		match BYTE_UNITS
+			.iter()
+			.find(|(_, scale)| self.0 != 0 && self.0 % scale == 0)
+		{
+			Some((unit, scale)) => write!(f, "{}{}", self.0 / scale, unit),
+			None => write!(f, "{}B", self.0),
+		}
+	}
+}
+
+impl From<Duration> for HumanDuration {
+	fn from(duration: Duration) -> Self { HumanDuration(duration) }
+}
+
+impl From<HumanDuration> for Duration {
+	fn from(duration: HumanDuration) -> Self { duration.0 }
+}
+
+impl From<u64> for ByteSize {
+	fn from(bytes: u64) -> Self { ByteSize(bytes) }
+}
+
+impl From<ByteSize> for u64 {
+	fn from(size: ByteSize) -> Self { size.0 }
+}
+
+impl serde::Serialize for HumanDuration {
+	fn serialize<S>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for ByteSize {
+	fn serialize<S>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error>
+	where
+		S: serde::Serializer,
+	{
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for HumanDuration {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		deserializer.deserialize_any(UnitVisitor::<HumanDuration>(std::marker::PhantomData))
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for ByteSize {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: serde::Deserializer<'de>,
+	{
+		deserializer.deserialize_any(UnitVisitor::<ByteSize>(std::marker::PhantomData))
+	}
+}
+
+/// Reads a unit type from a string, or a plain integer in its
+/// smallest config unit
+struct UnitVisitor<T>(std::marker::PhantomData<T>);
+
+/// The unit types [`UnitVisitor`] reads
+trait Unit: FromStr<Err = UnitError> {
+	/// What the type is called in error messages
+	const EXPECTED: &'static str;
+
+	fn from_integer(n: u64) -> Self;
+}
+
+impl Unit for HumanDuration {
+	const EXPECTED: &'static str = "a duration like \"30s\" or a number of seconds";
+
+	fn from_integer(n: u64) -> Self { HumanDuration(Duration::from_secs(n)) }
+}
+
+impl Unit for ByteSize {
+	const EXPECTED: &'static str = "a byte size like \"10MB\" or a number of bytes";
+
+	fn from_integer(n: u64) -> Self { ByteSize(n) }
+}
+
+impl<'de, T> serde::de::Visitor<'de> for UnitVisitor<T>
+where
+	T: Unit,
+{
+	type Value = T;
+
+	fn expecting(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		f.write_str(T::EXPECTED)
+	}
+
+	fn visit_str<E>(
+		self,
+		v: &str,
+	) -> Result<T, E>
+	where
+		E: serde::de::Error,
+	{
+		v.parse().map_err(E::custom)
+	}
+
+	fn visit_u64<E>(
+		self,
+		v: u64,
+	) -> Result<T, E>
+	where
+		E: serde::de::Error,
+	{
+		Ok(T::from_integer(v))
+	}
+
+	fn visit_i64<E>(
+		self,
+		v: i64,
+	) -> Result<T, E>
+	where
+		E: serde::de::Error,
+	{
+		if v < 0 {
+			return Err(E::invalid_value(serde::de::Unexpected::Signed(v), &self));
+		}
+		Ok(T::from_integer(v as u64))
+	}
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use snafu::ResultExt;
+
+use crate::{read_config, Config, Format, Result, Watch};
+
+/// How long the file has to be left alone after a change before it is
+/// read again, so editors writing it in several steps cause one
+/// reload
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Keeps watching a config file for as long as it is alive, see
+/// [`Config::watch`]
+///
+/// Dropping it stops the watcher, after which the callback isn't
+/// called again.
+pub struct ConfigWatcher {
+	_watcher: RecommendedWatcher,
+	path: PathBuf,
+}
+
+impl ConfigWatcher {
+	/// The path of the config file being watched
+	pub fn path(&self) -> &Path { &self.path }
+}
+
+/// Watch the config file at `path` and call `callback` with the
+/// config read from it after every change
+pub(crate) fn watch<C, F>(
+	format: Format,
+	path: PathBuf,
+	mut callback: F,
+) -> Result<ConfigWatcher>
+where
+	C: serde::de::DeserializeOwned + Config<C> + 'static,
+	F: FnMut(Result<C>) + Send + 'static,
+{
+	let (tx, rx) = channel();
+	let mut watcher = notify::watcher(tx, DEBOUNCE).context(Watch { path: &path })?;
+	// Editors often replace the file instead of writing to it, which a
+	// watch on the file itself doesn't survive, so watch its directory.
+	let dir = path.parent().unwrap_or_else(|| Path::new("."));
+	watcher
+		.watch(dir, RecursiveMode::NonRecursive)
+		.context(Watch { path: &path })?;
+
+	let file = path.clone();
+	std::thread::spawn(move || {
+		// The sender is dropped along with the watcher, which ends the
+		// loop.
+		for event in rx {
+			let changed = match &event {
+				DebouncedEvent::Create(p) | DebouncedEvent::Write(p) | DebouncedEvent::Rename(_, p) =>
+					p.file_name() == file.file_name(),
+				_ => false,
+			};
+			if changed {
+				callback(read_config(format, &file));
+			}
+		}
+	});
+
+	Ok(ConfigWatcher {
+		_watcher: watcher,
+		path,
+	})
+}